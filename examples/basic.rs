@@ -1,7 +1,8 @@
 use compo::prelude::*;
 
 fn main() {
-    run(app);
+    // Exit once all components have finished
+    Loop::new().exit_when_done().run(app);
 }
 
 #[component]
@@ -0,0 +1,34 @@
+use compo::prelude::*;
+
+fn main() {
+    // Exit once all components have finished
+    Loop::new().exit_when_done().run(app);
+}
+
+#[component]
+async fn app() {
+    #[catch] // Makes this component an error boundary, `error` holds the last failure (panic or error) of a descendant
+    let error;
+    if let Some(error) = error {
+        // The boundary re-renders after a failure, so a fallback can be rendered instead
+        eprintln!("Recovered from: {}", error);
+        #[render]
+        fallback {};
+    } else {
+        #[render]
+        faulty {};
+    }
+}
+
+#[component]
+async fn faulty() -> Result<(), String> {
+    // Components may return `Result<(), E>`, errors are reported to the nearest error boundary
+    println!("Faulty component rendered.");
+    sleep(Duration::from_millis(500)).await;
+    Err("something went wrong".to_owned())
+}
+
+#[component]
+async fn fallback() {
+    println!("Fallback component rendered.");
+}
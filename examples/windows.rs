@@ -207,5 +207,5 @@ fn main() {
     #[cfg(windows)]
     Loop::new()
         .register_poll_handler(win::handle_windows_message)
-        .run(win::app)
+        .run(win::app);
 }
@@ -1,7 +1,3 @@
-#[path = "macros.rs"]
-#[macro_use]
-mod macros;
-
 use {
     crate::utils::to_camel,
     proc_macro::{Delimiter, TokenStream, TokenTree},
@@ -80,7 +76,7 @@ pub(super) fn handle_arguments(
                     }
                 }
                 let mut argument_type = Vec::new();
-                while let Some(i) = iter.next() {
+                for i in iter.by_ref() {
                     if let TokenTree::Punct(p) = &i
                         && p.as_char() == ','
                     {
@@ -104,6 +100,7 @@ pub(super) fn handle_arguments(
                     .collect::<String>();
                 let mut default_value = Vec::new();
                 let mut is_event = false;
+                let mut no_eq = false;
                 let attrs_str = attrs
                     .iter()
                     .filter(|i| {
@@ -114,15 +111,18 @@ pub(super) fn handle_arguments(
                             && let Some(TokenTree::Punct(p)) = iter.next()
                             && p.as_char() == '='
                         {
-                            while let Some(i) = iter.next() {
-                                default_value.push(i);
-                            }
+                            default_value.extend(iter.by_ref());
                             false
                         } else if let Some(TokenTree::Ident(i)) = &item
                             && i.to_string() == "event"
                         {
                             is_event = true;
                             false
+                        } else if let Some(TokenTree::Ident(i)) = &item
+                            && i.to_string() == "no_eq"
+                        {
+                            no_eq = true;
+                            false
                         } else {
                             true
                         }
@@ -159,14 +159,14 @@ pub(super) fn handle_arguments(
                         property_name
                     ));
                     field_getters_and_setters.push(ts!(
-                        "{} pub fn get_{}(&self) -> &EventEmitter<'a, {}> {{\nunsafe {{ transmute(self.{}.get()) }}\n}}",
+                        "{} pub fn get_{}(&self) -> &EventEmitter<'a, {}> {{\nunsafe {{ &*self.{}.get() }}\n}}",
                         attrs_str,
                         property_name,
                         property_type.replace("&", "&'a "),
                         property_name
                     ));
                     field_getters_and_setters.push(ts!(
-                        "{} pub fn set_{}(&self, value: &EventListener<'a, {}>) {{\nunsafe {{\n*self.{}.get() = value.new_emitter()\n}}\n}}",
+                        "{} pub fn set_{}(&self, value: &EventListener<'a, {}>) -> bool {{\nunsafe {{\n*self.{}.get() = value.new_emitter()\n}}\ntrue\n}}",
                         attrs_str,
                         property_name,
                         property_type.replace("&", "&'a "),
@@ -185,18 +185,34 @@ pub(super) fn handle_arguments(
                         property_default_value
                     ));
                     field_getters_and_setters.push(ts!(
-                        "{} pub fn get_{}(&self) -> &{} {{\nunsafe {{ transmute(self.{}.get()) }}\n}}",
+                        "{} pub fn get_{}(&self) -> &{} {{\nunsafe {{ &*self.{}.get() }}\n}}",
                         attrs_str,
                         property_name,
                         property_type.replace("&", "&'a "),
                         property_name
                     ));
+                    // 属性可以是任何实现了 Clone 的类型，父组件放弃所有权时可以直接移入。
+                    // setter 返回属性是否发生了变化，值相等时子组件不需要重新渲染
+                    let (unchanged, unchanged_owned) = if no_eq {
+                        ("false", "false")
+                    } else {
+                        ("*property == *value", "*property == value")
+                    };
                     field_getters_and_setters.push(ts!(
-                        "{} pub fn set_{}(&self, value: &{}) {{\nunsafe {{ *self.{}.get() = *value }}\n}}",
+                        "{} #[allow(clippy::ptr_arg)]\npub fn set_{}(&self, value: &{}) -> bool {{\nlet property = unsafe {{ &mut *self.{}.get() }};\nif {} {{\nreturn false;\n}}\n*property = value.clone();\ntrue\n}}",
                         attrs_str,
                         property_name,
                         property_type.replace("&", "&'a "),
-                        property_name
+                        property_name,
+                        unchanged
+                    ));
+                    field_getters_and_setters.push(ts!(
+                        "{} pub fn set_{}_owned(&self, value: {}) -> bool {{\nlet property = unsafe {{ &mut *self.{}.get() }};\nif {} {{\nreturn false;\n}}\n*property = value;\ntrue\n}}",
+                        attrs_str,
+                        property_name,
+                        property_type.replace("&", "&'a "),
+                        property_name,
+                        unchanged_owned
                     ))
                 }
             }
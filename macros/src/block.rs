@@ -1,19 +1,83 @@
-#[path = "macros.rs"]
-#[macro_use]
-mod macros;
 mod recurse;
 mod stmt;
 
-use {
-    crate::utils::to_camel, proc_macro::TokenStream, recurse::handle_block_recursively,
-    std::collections::HashMap,
-};
+use {proc_macro::TokenStream, recurse::handle_block_recursively, std::collections::HashMap};
+
+/// A lifecycle hook block: the hook name and the body of the block.
+pub(super) type Hook = (String, TokenStream);
+
+/// The `(group, arm)` pairs of the branches enclosing a position in the component body.
+type BranchPath = Vec<(usize, usize)>;
+
+/// The conditional branches (`if`/`else` blocks and `match` arms) of a component body.
+#[derive(Default)]
+pub(super) struct Branches {
+    groups: usize,
+    /// The branches enclosing the current position.
+    path: BranchPath,
+    /// The conditionally rendered children, the branches enclosing them and whether they are
+    /// keyed.
+    slots: Vec<(String, BranchPath, bool)>,
+    /// The number of branches enclosing each enclosing loop, and the keyed children rendered
+    /// directly in it.
+    loops: Vec<(usize, Vec<String>)>,
+}
+
+impl Branches {
+    fn new_group(&mut self) -> usize {
+        self.groups += 1;
+        self.groups - 1
+    }
+
+    fn is_conditional(&self, component_id: &str) -> bool {
+        self.slots.iter().any(|(id, _, _)| id == component_id)
+    }
+}
+
+/// A `#[memo] let` of a component body.
+pub(super) struct Memo {
+    pub(super) name: String,
+    /// The field caching the value.
+    pub(super) id: String,
+    /// The variables the value is computed from.
+    pub(super) inputs: Vec<String>,
+    pub(super) expr: String,
+}
+
+impl Memo {
+    /// Returns the expression reading the cached value, computing it again if the inputs
+    /// changed or if `refresh` is set.
+    fn value(&self, refresh: bool) -> String {
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|i| format!("{}.clone(),", i))
+            .collect::<String>();
+        format!(
+            "this.{}.borrow_mut().{}(({}), || {{\n{}\n}})",
+            self.id,
+            if refresh { "refresh" } else { "get" },
+            inputs,
+            self.expr
+        )
+    }
+}
+
+/// The parts of a component generated from its body.
+pub(super) struct Block {
+    pub(super) body: TokenStream,
+    pub(super) field_defines: Vec<TokenStream>,
+    pub(super) field_initializers: Vec<TokenStream>,
+    pub(super) hooks: Vec<Hook>,
+    /// Statements of the `unmount` method, unmounting every child component.
+    pub(super) unmounts: Vec<TokenStream>,
+}
 
 pub(super) fn handle_block(
     stream: TokenStream,
     var_bindings: &mut Vec<TokenStream>,
-    component_name: &str,
-) -> (TokenStream, Vec<TokenStream>, Vec<TokenStream>) {
+    is_fallible: bool,
+) -> Block {
     let mut has_attr = false;
     let mut attrs = Vec::new();
     let mut field_defines = Vec::new();
@@ -21,6 +85,10 @@ pub(super) fn handle_block(
     let mut stmts = Vec::new();
     let mut component_name_index = 0;
     let mut refer_to_component = HashMap::new();
+    let mut hooks = Vec::new();
+    let mut branches = Branches::default();
+    let mut unmounts = Vec::new();
+    let mut memos = Vec::new();
 
     let error = handle_block_recursively(
         &stream,
@@ -32,24 +100,47 @@ pub(super) fn handle_block(
         &mut component_name_index,
         &mut stmts,
         &mut refer_to_component,
+        &mut hooks,
+        &mut branches,
+        &mut unmounts,
+        &mut memos,
+        None,
     );
     if !error.is_empty() {
-        return (error, Default::default(), Default::default());
+        return Block {
+            body: error,
+            field_defines: Default::default(),
+            field_initializers: Default::default(),
+            hooks: Default::default(),
+            unmounts: Default::default(),
+        };
     }
+    // 组件第一次渲染时调用 on_mount 钩子，在此之前的更新不调用 on_update 钩子
+    let has_hook = |name: &str| hooks.iter().any(|(h, _)| h == name);
+    let mount = if has_hook("on_mount") {
+        "if !this._mounted.replace(true) {\nthis.on_mount();\n}"
+    } else if has_hook("on_update") {
+        "this._mounted.set(true);"
+    } else {
+        ""
+    };
     let var_bindings = var_bindings
         .iter()
         .map(|i| i.to_string())
         .collect::<String>();
     let stmts = stmts.iter().map(|i| format!("{} ", i)).collect::<String>();
 
-    (
-        ts!(
-            "{{\nlet Some(this) = this.upgrade() else {{\neprintln!(\"{} dropped.\");\nreturn;\n}};\n{}\n{}\n}}",
-            to_camel(&component_name),
+    Block {
+        body: ts!(
+            "{{\nlet Some(this) = this.upgrade() else {{\nreturn{};\n}};\n{}\n{}\n{}\n}}",
+            if is_fallible { " Ok(())" } else { "" },
+            mount,
             var_bindings,
             stmts
         ),
         field_defines,
         field_initializers,
-    )
+        hooks,
+        unmounts,
+    }
 }
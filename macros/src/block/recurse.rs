@@ -1,10 +1,71 @@
 use {
-    super::stmt::handle_stmt,
+    super::{
+        Branches, Hook, Memo,
+        stmt::{handle_stmt, memo_inputs},
+    },
     crate::utils::{take_while, to_camel},
-    proc_macro::{Delimiter, Punct, Spacing, TokenStream, TokenTree},
-    std::collections::HashMap,
+    proc_macro::{Delimiter, Punct, Spacing, Span, TokenStream, TokenTree},
+    std::{collections::HashMap, mem::take},
 };
 
+/// The lifecycle hooks that can be declared as attributed blocks in a component body.
+const HOOKS: [&str; 3] = ["on_mount", "on_update", "on_unmount"];
+
+/// Returns the key expression of a `render(key = ...)` attribute, or `None` for other attributes.
+fn render_key(attr: &TokenStream) -> Option<Result<TokenStream, Span>> {
+    let mut iter = attr.clone().into_iter();
+    match (iter.next(), iter.next()) {
+        (Some(TokenTree::Ident(i)), Some(TokenTree::Group(g)))
+            if i.to_string() == "render" && g.delimiter() == Delimiter::Parenthesis =>
+        {
+            let mut iter = g.stream().into_iter();
+            match (iter.next(), iter.next()) {
+                (Some(TokenTree::Ident(k)), Some(TokenTree::Punct(p)))
+                    if k.to_string() == "key" && p.as_char() == '=' =>
+                {
+                    let key = TokenStream::from_iter(iter);
+                    if key.is_empty() {
+                        Some(Err(p.span()))
+                    } else {
+                        Some(Ok(key))
+                    }
+                }
+                _ => Some(Err(g.span())),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Returns the statements unmounting the children rendered in the arms of `group` other than
+/// `arm` (or in every arm if `arm` is `None`).
+fn unmount_arms(branches: &Branches, group: usize, arm: Option<usize>) -> String {
+    branches
+        .slots
+        .iter()
+        .filter(|(_, path, _)| path.iter().any(|(g, a)| *g == group && Some(*a) != arm))
+        .map(|(id, _, keyed)| {
+            if *keyed {
+                format!("this.{}.take().clear();\n", id)
+            } else {
+                format!("if let Some(c) = this.{}.take() {{\nc.unmount();\n}}\n", id)
+            }
+        })
+        .collect()
+}
+
+/// Wraps the statements from `index` on into a single block, the body of a `match` arm that
+/// has no block of its own.
+fn wrap_arm_body(stmts: &mut Vec<TokenStream>, index: usize) {
+    let body = stmts
+        .split_off(index)
+        .iter()
+        .map(|i| format!("{} ", i))
+        .collect::<String>();
+    stmts.push(ts!("{{\n{}\n}}", body));
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(super) fn handle_block_recursively(
     stream: &TokenStream,
     has_attr: &mut bool,
@@ -15,9 +76,21 @@ pub(super) fn handle_block_recursively(
     component_name_index: &mut u32,
     stmts: &mut Vec<TokenStream>,
     refer_to_component: &mut HashMap<String, HashMap<String, Vec<String>>>,
+    hooks: &mut Vec<Hook>,
+    branches: &mut Branches,
+    unmounts: &mut Vec<TokenStream>,
+    memos: &mut Vec<Memo>,
+    match_group: Option<usize>,
 ) -> TokenStream {
     let mut iter = stream.clone().into_iter();
     let mut stmt = Vec::new();
+    // 当前 if/else 链的分支组和下一个分支的序号
+    let mut if_chain = None;
+    let mut match_arm = 0;
+    // 当前不带代码块的 `match` 分支：表达式在 stmts 中的起始位置以及所属的分支
+    let mut expr_arm = None;
+    // 本层中的分支代码块：在 stmts 中的位置以及所属的分支
+    let mut arm_bodies = Vec::new();
 
     while let Some(tree) = iter.next() {
         match tree {
@@ -34,18 +107,187 @@ pub(super) fn handle_block_recursively(
                     .iter()
                     .find(|i| {
                         let s = i.to_string();
-                        &s == "field" || &s == "render"
+                        &s == "field"
+                            || &s == "render"
+                            || &s == "catch"
+                            || &s == "memo"
+                            || HOOKS.contains(&&*s)
+                            || render_key(i).is_some()
                     })
                     .is_some() =>
             {
+                // 生命周期钩子：`#[on_mount] { ... }` 等代码块会被移到生成的 trait 方法中
+                if let Some(hook) = attrs.iter().find_map(|i| {
+                    let s = i.to_string();
+                    HOOKS.iter().find(|h| **h == s)
+                }) {
+                    let TokenTree::Group(g) = &t else {
+                        return error!(
+                            block,
+                            t.span(),
+                            "Expected block after `#[{}]`, got `{}`",
+                            hook,
+                            t
+                        );
+                    };
+                    if g.delimiter() != Delimiter::Brace {
+                        return error!(block, g.span(), "Expected block after `#[{}]`", hook);
+                    }
+                    hooks.push((hook.to_string(), g.stream()));
+                    attrs.clear();
+                    continue;
+                }
+
                 let mut is_field = false;
                 let mut is_render = false;
+                let mut is_catch = false;
+                let mut is_memo = false;
+                let mut key = None;
+                for i in attrs.iter() {
+                    match render_key(i) {
+                        Some(Ok(k)) => key = Some(k),
+                        Some(Err(span)) => {
+                            return error!(block, span, "Expected `key = <expr>`");
+                        }
+                        None => (),
+                    }
+                }
                 attrs.retain(|i| {
                     let s = i.to_string();
                     is_field |= &s == "field";
                     is_render |= &s == "render";
-                    &s != "field" && &s != "render"
+                    is_catch |= &s == "catch";
+                    is_memo |= &s == "memo";
+                    &s != "field"
+                        && &s != "render"
+                        && &s != "catch"
+                        && &s != "memo"
+                        && render_key(i).is_none()
                 });
+                is_render |= key.is_some();
+
+                if is_catch {
+                    if let TokenTree::Ident(i) = &t
+                        && i.to_string() != "let"
+                    {
+                        return error!(
+                            block,
+                            i.span(),
+                            "Catch attribute must be used with `let` var."
+                        );
+                    }
+                    let Some(TokenTree::Ident(ident)) = iter.next() else {
+                        return error!(block, "Expected ident (error name)");
+                    };
+                    let error_name = ident.to_string();
+                    match iter.next() {
+                        Some(TokenTree::Punct(p)) if p.as_char() == ';' => (),
+                        Some(t) => {
+                            return error!(
+                                block,
+                                t.span(),
+                                "Expected ';', got `{}` (error `{}` can not be initialized)",
+                                t,
+                                error_name
+                            );
+                        }
+                        None => return error!(block, "Expected ';', got eof"),
+                    }
+
+                    let attrs = attrs
+                        .iter()
+                        .map(|i| format!("#[{}]", i))
+                        .collect::<String>();
+                    // 将当前组件注册为错误边界，捕获到子组件的失败后重新渲染自身
+                    var_bindings.push(ts!(
+                        "if let Some(rt) = this.get_rt().upgrade() {{\nlet this = Rc::downgrade(&this);\nrt.catch_failures(move |failure| {{\nif let Some(this) = this.upgrade() {{\nthis.{}.replace(Some(Rc::new(failure)));\nthis.update();\n}}\n}});\n}}\n{} let {} = this.{}.borrow().clone();",
+                        error_name,
+                        attrs,
+                        error_name,
+                        error_name
+                    ));
+                    field_defines.push(ts!(
+                        "{} {}: RefCell<Option<Rc<Failure>>>,",
+                        attrs,
+                        error_name
+                    ));
+                    field_initializers.push(ts!("{} {}: Default::default(),", attrs, error_name));
+                }
+
+                if is_memo {
+                    if let TokenTree::Ident(i) = &t
+                        && i.to_string() != "let"
+                    {
+                        return error!(
+                            block,
+                            i.span(),
+                            "Memo attribute must be used with `let` var."
+                        );
+                    }
+                    let mut ident = iter.next();
+                    if let Some(TokenTree::Ident(i)) = &ident
+                        && i.to_string() == "mut"
+                    {
+                        ident = iter.next();
+                    }
+                    let Some(TokenTree::Ident(ident)) = ident else {
+                        return error!(block, "Expected ident (memo name)");
+                    };
+                    let memo_name = ident.to_string();
+                    let memo_type = match iter.next() {
+                        Some(TokenTree::Punct(p)) if p.as_char() == '=' => String::new(),
+                        Some(TokenTree::Punct(p)) if p.as_char() == ':' => match take_while(
+                            &mut iter,
+                            &TokenTree::Punct(Punct::new('=', Spacing::Alone)),
+                        ) {
+                            Err(s) => return error!(block, s, "Expected '=', got eof"),
+                            Ok(f) => format!(": {}", f),
+                        },
+                        Some(t) => {
+                            return error!(
+                                block,
+                                t.span(),
+                                "Expected '=', got `{}` (memo `{}` must be initialized with a value)",
+                                t,
+                                memo_name
+                            );
+                        }
+                        None => return error!(block, "Expected '=', got eof"),
+                    };
+                    let memo_value = match take_while(
+                        &mut iter,
+                        &TokenTree::Punct(Punct::new(';', Spacing::Alone)),
+                    ) {
+                        Err(s) => return error!(block, s, "Expected ';', got eof"),
+                        Ok(f) if f.is_empty() => {
+                            return error!(block, t.span(), "Expected expr (memo value), got ';'");
+                        }
+                        Ok(f) => f,
+                    };
+
+                    let attrs = attrs
+                        .iter()
+                        .map(|i| format!("#[{}]", i))
+                        .collect::<String>();
+                    // 值缓存在字段中，只有输入变化时才重新计算；输入在函数体中被修改时，
+                    // 重新计算的值会像普通变量一样传递给使用它的子组件
+                    let memo = Memo {
+                        name: memo_name,
+                        id: format!("_memo_{}", memos.len()),
+                        inputs: memo_inputs(&Vec::from_iter(memo_value.clone())),
+                        expr: memo_value.to_string(),
+                    };
+                    field_defines.push(ts!("{} {}: RefCell<Memoized>,", attrs, memo.id));
+                    field_initializers.push(ts!("{} {}: Default::default(),", attrs, memo.id));
+                    stmts.push(ts!(
+                        "{} #[allow(unused_mut)]\nlet mut {}{} = {};",
+                        attrs,
+                        memo.name,
+                        memo_type,
+                        memo.value(false)
+                    ));
+                    memos.push(memo);
+                }
 
                 if is_field {
                     if let TokenTree::Ident(i) = &t
@@ -119,7 +361,7 @@ pub(super) fn handle_block_recursively(
                         .map(|i| format!("#[{}]", i))
                         .collect::<String>();
                     var_bindings.push(ts!(
-                        "{} let {}: &mut {} = unsafe {{ transmute(this.{}.get()) }};",
+                        "{} let {}: &mut {} = unsafe {{ &mut *this.{}.get() }};",
                         attrs,
                         field_name,
                         field_type,
@@ -159,21 +401,102 @@ pub(super) fn handle_block_recursively(
                         .iter()
                         .map(|i| format!("#[{}]", i))
                         .collect::<String>();
-                    field_defines.push(ts!(
-                        "{} {}: Rc<{}<'a>>,",
-                        attrs,
-                        component_id,
-                        component_name_camel
-                    ));
-                    field_initializers.push(ts!(
-                        "{} {}: {}::new(rt.clone()).into(),",
-                        attrs,
-                        component_id,
-                        component_name_camel
-                    ));
+                    // 条件分支中的子组件在分支生效时才创建，分支失效时被卸载
+                    let is_conditional = !branches.path.is_empty();
+                    let mut render_stmts = Vec::new();
+                    if let Some(key) = &key {
+                        // 带有 key 的子组件按 key 保存在集合中，由所在的循环维护
+                        let Some((depth, keyed)) = branches.loops.last_mut() else {
+                            return error!(
+                                block,
+                                t.span(),
+                                "Keyed render must be used inside a `for`, `while` or `loop` block"
+                            );
+                        };
+                        keyed.push(component_id.clone());
+                        field_defines.push(ts!(
+                            "{} {}: RefCell<Keyed<{}<'a>>>,",
+                            attrs,
+                            component_id,
+                            component_name_camel
+                        ));
+                        field_initializers.push(ts!(
+                            "{} {}: Default::default(),",
+                            attrs,
+                            component_id
+                        ));
+                        unmounts.push(ts!("{} self.{}.take().clear();", attrs, component_id));
+                        render_stmts.push(format!(
+                            "let c = this.{}.borrow_mut().get_or_insert({}, || Rc::new({}::new(this.get_rt())));",
+                            component_id, key, component_name_camel
+                        ));
+                        // 循环中的分支只决定某一项是否存在，由集合在循环结束时处理；
+                        // 只有循环外的分支失效时才清空整个集合
+                        let path = branches.path[..*depth].to_vec();
+                        if !path.is_empty() {
+                            branches.slots.push((component_id.clone(), path, true));
+                        }
+                    } else if is_conditional {
+                        field_defines.push(ts!(
+                            "{} {}: RefCell<Option<Rc<{}<'a>>>>,",
+                            attrs,
+                            component_id,
+                            component_name_camel
+                        ));
+                        field_initializers.push(ts!(
+                            "{} {}: Default::default(),",
+                            attrs,
+                            component_id
+                        ));
+                        unmounts.push(ts!(
+                            "{} if let Some(c) = self.{}.take() {{\nc.unmount();\n}}",
+                            attrs,
+                            component_id
+                        ));
+                        render_stmts.push(format!(
+                            "let c = this.{}.borrow_mut().get_or_insert_with(|| Rc::new({}::new(this.get_rt()))).clone();",
+                            component_id, component_name_camel
+                        ));
+                        branches
+                            .slots
+                            .push((component_id.clone(), branches.path.clone(), false));
+                    } else {
+                        field_defines.push(ts!(
+                            "{} {}: Rc<{}<'a>>,",
+                            attrs,
+                            component_id,
+                            component_name_camel
+                        ));
+                        field_initializers.push(ts!(
+                            "{} {}: {}::new(rt.clone()).into(),",
+                            attrs,
+                            component_id,
+                            component_name_camel
+                        ));
+                        unmounts.push(ts!("{} self.{}.unmount();", attrs, component_id));
+                        render_stmts.push(format!("let c = &this.{};", component_id));
+                    }
 
                     let mut iter = g.stream().into_iter();
-                    while let Some(i) = iter.next() {
+                    while let Some(mut i) = iter.next() {
+                        // `#[owned] name: value` 将值移入子组件
+                        let mut is_owned = false;
+                        if let TokenTree::Punct(p) = &i
+                            && p.as_char() == '#'
+                        {
+                            match (iter.next(), iter.next()) {
+                                (Some(TokenTree::Group(g)), Some(next))
+                                    if g.delimiter() == Delimiter::Bracket
+                                        && g.stream().to_string() == "owned" =>
+                                {
+                                    is_owned = true;
+                                    i = next;
+                                }
+                                _ => {
+                                    return error!(block, p.span(), "Expected `#[owned]` property");
+                                }
+                            }
+                        }
                         let property_name = i.to_string();
                         let property_value = match iter.next() {
                             Some(TokenTree::Punct(p)) if p.as_char() == ',' => {
@@ -186,7 +509,7 @@ pub(super) fn handle_block_recursively(
                                     &TokenTree::Punct(Punct::new(',', Spacing::Alone)),
                                 ) {
                                     Ok(f) => f.to_string(),
-                                    Err(_) => iter2.map(|i| i.to_string()).collect(),
+                                    Err(_) => TokenStream::from_iter(iter2).to_string(),
                                 }
                             }
                             Some(t) => {
@@ -205,12 +528,19 @@ pub(super) fn handle_block_recursively(
                                 );
                             }
                         };
-                        stmts.push(ts!(
-                            "this.{}.set_{}(&{});",
-                            component_id,
-                            property_name,
-                            property_value
-                        ));
+                        // 被移走的变量不再被跟踪
+                        if is_owned {
+                            render_stmts.push(format!(
+                                "c.set_{}_owned({});",
+                                property_name, property_value
+                            ));
+                            continue;
+                        }
+                        render_stmts.push(format!("c.set_{}(&{});", property_name, property_value));
+                        // 列表中的子组件的属性通常来自循环变量，不跟踪它们的赋值
+                        if key.is_some() {
+                            continue;
+                        }
                         if !refer_to_component.contains_key(&property_value) {
                             refer_to_component.insert(property_value.clone(), Default::default());
                         }
@@ -223,28 +553,87 @@ pub(super) fn handle_block_recursively(
                             }
                         }
                     }
-                    stmts.push(ts!(
-                        "this.spawn({}(Rc::downgrade(&this.{})));",
-                        component_name,
-                        component_id
-                    ));
+                    render_stmts.push("c.rerender();".to_owned());
+                    stmts.push(ts!("{} {{\n{}\n}}", attrs, render_stmts.join("\n")));
                     *component_name_index += 1;
                 }
 
                 attrs.clear();
             }
             t => {
+                // 不带代码块的 `match` 分支在逗号处结束
+                if let TokenTree::Punct(p) = &t
+                    && p.as_char() == ','
+                    && let Some((index, arm)) = expr_arm.take()
+                {
+                    handle_stmt(stmts, &mut stmt, refer_to_component, branches, memos);
+                    wrap_arm_body(stmts, index);
+                    branches.path.pop();
+                    arm_bodies.push((stmts.len() - 1, arm));
+                    stmts.push(ts!(","));
+                    continue;
+                }
                 stmt.push(t.clone());
                 if let TokenTree::Punct(p) = &t
                     && p.as_char() == ';'
                 {
-                    handle_stmt(stmts, &mut stmt, refer_to_component);
+                    handle_stmt(stmts, &mut stmt, refer_to_component, branches, memos);
+                } else if let Some(group) = match_group
+                    && expr_arm.is_none()
+                    && stmt.len() >= 2
+                    && stmt[stmt.len() - 2].to_string() == "="
+                    && stmt[stmt.len() - 1].to_string() == ">"
+                    && !matches!(iter.clone().next(), Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Brace)
+                {
+                    // 不带代码块的 `match` 分支（如 `_ => ()`）同样是条件分支，
+                    // 它的表达式会被放进代码块中，以便卸载其他分支的子组件
+                    stmts.push(TokenStream::from_iter(take(&mut stmt)));
+                    let arm = (group, match_arm);
+                    match_arm += 1;
+                    branches.path.push(arm);
+                    expr_arm = Some((stmts.len(), arm));
                 } else if let TokenTree::Group(g) = &t
                     && g.delimiter() == Delimiter::Brace
                 {
                     stmt.pop();
-                    stmts.push(TokenStream::from_iter(stmt.clone().into_iter()));
+                    // `if`/`else` 的代码块和 `match` 的分支是条件分支
+                    let first = stmt.first().map(|i| i.to_string());
+                    let is_match_arm = stmt.len() >= 2
+                        && stmt[stmt.len() - 2].to_string() == "="
+                        && stmt[stmt.len() - 1].to_string() == ">";
+                    let is_final_else = stmt.len() == 1 && first.as_deref() == Some("else");
+                    let arm = match first.as_deref() {
+                        Some("if") => {
+                            let group = branches.new_group();
+                            if_chain = Some((group, 1));
+                            Some((group, 0))
+                        }
+                        Some("else") => if_chain.take().map(|(group, arm)| {
+                            if_chain = Some((group, arm + 1));
+                            (group, arm)
+                        }),
+                        _ => match match_group {
+                            Some(group) if is_match_arm => {
+                                match_arm += 1;
+                                Some((group, match_arm - 1))
+                            }
+                            _ => None,
+                        },
+                    };
+                    let inner_match_group = if first.as_deref() == Some("match") {
+                        Some(branches.new_group())
+                    } else {
+                        None
+                    };
+                    let is_loop = matches!(first.as_deref(), Some("for" | "while" | "loop"));
+                    if is_loop {
+                        branches.loops.push((branches.path.len(), Vec::new()));
+                    }
+                    stmts.push(TokenStream::from_iter(stmt.clone()));
                     stmt.clear();
+                    if let Some(arm) = arm {
+                        branches.path.push(arm);
+                    }
                     let mut stmts2 = Vec::new();
                     let error = handle_block_recursively(
                         &g.stream(),
@@ -256,9 +645,35 @@ pub(super) fn handle_block_recursively(
                         component_name_index,
                         &mut stmts2,
                         refer_to_component,
+                        hooks,
+                        branches,
+                        unmounts,
+                        memos,
+                        inner_match_group,
                     );
+                    if let Some(arm) = arm {
+                        branches.path.pop();
+                        arm_bodies.push((stmts.len(), arm));
+                    }
                     let stmts2 = stmts2.iter().map(|i| format!("{} ", i)).collect::<String>();
                     stmts.push(ts!("{{\n{}\n}}", stmts2));
+                    // `if` 链只能由紧跟在代码块之后的 `else` 延续；没有 `else` 分支时，
+                    // 条件不成立也要卸载各分支中的子组件
+                    let continues = matches!(iter.clone().next(), Some(TokenTree::Ident(i)) if i.to_string() == "else");
+                    if !continues && let Some((group, _)) = if_chain.take() {
+                        let unmounts = unmount_arms(branches, group, None);
+                        if !is_final_else && !unmounts.is_empty() {
+                            stmts.push(ts!("else {{\n{}\n}}", unmounts));
+                        }
+                    }
+                    // 循环开始前和结束后更新循环中带有 key 的子组件集合
+                    if is_loop && let Some((_, keyed)) = branches.loops.pop() {
+                        let index = stmts.len() - 2;
+                        for id in keyed {
+                            stmts.insert(index, ts!("this.{}.borrow_mut().begin();", id));
+                            stmts.push(ts!("this.{}.borrow_mut().finish();", id));
+                        }
+                    }
                     if !error.is_empty() {
                         return error;
                     }
@@ -267,7 +682,20 @@ pub(super) fn handle_block_recursively(
         }
     }
     if stmt.iter().last().is_some() {
-        handle_stmt(stmts, &mut stmt, refer_to_component);
+        handle_stmt(stmts, &mut stmt, refer_to_component, branches, memos);
+    }
+    if let Some((index, arm)) = expr_arm {
+        wrap_arm_body(stmts, index);
+        branches.path.pop();
+        arm_bodies.push((stmts.len() - 1, arm));
+    }
+
+    // 进入一个分支时，卸载同一组中其他分支的子组件
+    for (index, (group, arm)) in arm_bodies {
+        let unmounts = unmount_arms(branches, group, Some(arm));
+        if !unmounts.is_empty() {
+            stmts[index] = ts!("{{\n{}\n{}\n}}", unmounts, stmts[index]);
+        }
     }
 
     Default::default()
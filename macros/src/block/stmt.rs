@@ -1,28 +1,259 @@
 use {
-    proc_macro::{TokenStream, TokenTree},
+    super::{Branches, Memo},
+    proc_macro::{Delimiter, Spacing, TokenStream, TokenTree},
     std::collections::HashMap,
 };
 
+/// Methods of the standard strings and collections that mutate their receiver.
+const MUTATING_METHODS: [&str; 27] = [
+    "append",
+    "clear",
+    "dedup",
+    "drain",
+    "entry",
+    "extend",
+    "fill",
+    "get_mut",
+    "insert",
+    "iter_mut",
+    "pop",
+    "pop_back",
+    "pop_front",
+    "push",
+    "push_back",
+    "push_front",
+    "push_str",
+    "remove",
+    "reverse",
+    "resize",
+    "retain",
+    "sort",
+    "sort_by",
+    "sort_by_key",
+    "sort_unstable",
+    "swap_remove",
+    "truncate",
+];
+
+/// Identifiers in a memo expression that are not variables: keywords and primitive types.
+const NOT_VARIABLES: [&str; 51] = [
+    "as", "async", "await", "bool", "break", "char", "const", "continue", "crate", "dyn", "else",
+    "enum", "f32", "f64", "false", "fn", "for", "i128", "i16", "i32", "i64", "i8", "if", "impl",
+    "in", "isize", "let", "loop", "match", "move", "mut", "ref", "return", "self", "static", "str",
+    "struct", "super", "this", "true", "u128", "u16", "u32", "u64", "u8", "unsafe", "use", "usize",
+    "where", "while", "type",
+];
+
+fn is_punct(tree: Option<&TokenTree>, c: char, spacing: Option<Spacing>) -> bool {
+    matches!(tree, Some(TokenTree::Punct(p)) if p.as_char() == c && spacing.is_none_or(|s| p.spacing() == s))
+}
+
+/// Returns the length of the assignment operator (`=`, `+=`, `<<=` ...) at the start of
+/// `trees`, or 0 if there is none.
+fn assignment_len(trees: &[TokenTree]) -> usize {
+    let alone = Some(Spacing::Alone);
+    let joint = Some(Spacing::Joint);
+    if is_punct(trees.first(), '=', alone) {
+        return 1;
+    }
+    if "+-*/%^&|"
+        .chars()
+        .any(|c| is_punct(trees.first(), c, joint))
+        && is_punct(trees.get(1), '=', alone)
+    {
+        return 2;
+    }
+    if ['<', '>']
+        .iter()
+        .any(|c| is_punct(trees.first(), *c, joint) && is_punct(trees.get(1), *c, joint))
+        && is_punct(trees.get(2), '=', alone)
+    {
+        return 3;
+    }
+
+    0
+}
+
+/// Checks if the variable at `trees[index]` is changed by the statement: assigned (also through
+/// a field or an index), or the receiver of a mutating method.
+fn is_changed(trees: &[TokenTree], index: usize) -> bool {
+    if index > 0 && is_punct(trees.get(index - 1), '.', None) {
+        return false;
+    }
+    let mut i = index + 1;
+    loop {
+        match (trees.get(i), trees.get(i + 1), trees.get(i + 2)) {
+            (
+                Some(TokenTree::Punct(p)),
+                Some(TokenTree::Ident(method)),
+                Some(TokenTree::Group(g)),
+            ) if p.as_char() == '.' && g.delimiter() == Delimiter::Parenthesis => {
+                return MUTATING_METHODS.contains(&method.to_string().as_str());
+            }
+            (Some(TokenTree::Punct(p)), Some(TokenTree::Ident(_)), _) if p.as_char() == '.' => {
+                i += 2
+            }
+            (Some(TokenTree::Group(g)), _, _) if g.delimiter() == Delimiter::Bracket => i += 1,
+            _ => return assignment_len(&trees[i..]) > 0,
+        }
+    }
+}
+
+/// Returns the variables a `#[memo]` expression is computed from: the identifiers that are not
+/// keywords, types, paths, fields, methods, macros or bindings of the expression itself.
+pub(super) fn memo_inputs(trees: &[TokenTree]) -> Vec<String> {
+    fn collect(trees: &[TokenTree], bound: &mut Vec<String>, inputs: &mut Vec<String>) {
+        let mut in_pattern = false;
+        let mut in_closure_params = false;
+        let mut skip_next = false;
+        for (index, tree) in trees.iter().enumerate() {
+            if skip_next {
+                skip_next = false;
+                continue;
+            }
+            let previous = index.checked_sub(1).and_then(|i| trees.get(i));
+            let next = trees.get(index + 1);
+            let ident = match tree {
+                TokenTree::Group(g) => {
+                    collect(&Vec::from_iter(g.stream()), bound, inputs);
+                    continue;
+                }
+                // 闭包参数只能出现在表达式的开头，其他位置的 `|` 是按位或运算符；`||` 是没有参数的闭包
+                TokenTree::Punct(p) if p.as_char() == '|' => {
+                    if in_closure_params {
+                        in_closure_params = false;
+                        in_pattern = false;
+                    } else if previous.is_none()
+                        || is_punct(previous, ',', None)
+                        || is_punct(previous, '=', None)
+                        || is_punct(previous, ';', None)
+                        || matches!(previous, Some(TokenTree::Ident(i)) if i.to_string() == "move")
+                    {
+                        if p.spacing() == Spacing::Joint && is_punct(next, '|', None) {
+                            skip_next = true;
+                        } else {
+                            in_closure_params = true;
+                            in_pattern = true;
+                        }
+                    }
+                    continue;
+                }
+                TokenTree::Punct(p) if in_pattern && p.as_char() == '=' => {
+                    in_pattern = false;
+                    continue;
+                }
+                TokenTree::Ident(i) => i.to_string(),
+                _ => continue,
+            };
+            if ident == "let" || ident == "for" {
+                in_pattern = true;
+                continue;
+            }
+            if ident == "in" {
+                in_pattern = false;
+            }
+            if in_pattern {
+                bound.push(ident);
+            } else if !NOT_VARIABLES.contains(&ident.as_str())
+                && !ident.starts_with(|c: char| c.is_uppercase())
+                && !is_punct(previous, '.', None)
+                && !is_punct(previous, ':', None)
+                && !is_punct(previous, '\'', None)
+                && !matches!(previous, Some(TokenTree::Ident(i)) if i.to_string() == "as")
+                && !is_punct(next, '!', None)
+                && !is_punct(next, ':', None)
+                && !matches!(next, Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Parenthesis)
+                && !bound.contains(&ident)
+                && !inputs.contains(&ident)
+            {
+                inputs.push(ident);
+            }
+        }
+    }
+
+    let mut inputs = Vec::new();
+    collect(trees, &mut Vec::new(), &mut inputs);
+
+    inputs
+}
+
 pub(super) fn handle_stmt(
     stmts: &mut Vec<TokenStream>,
     stmt: &mut Vec<TokenTree>,
     refer_to_component: &mut HashMap<String, HashMap<String, Vec<String>>>,
+    branches: &Branches,
+    memos: &[Memo],
 ) {
     stmts.push(TokenStream::from_iter(stmt.clone()));
-    let mut iter = stmt.iter();
-    while let Some(i) = iter.next() {
-        if let TokenTree::Ident(ident) = i
-            && let Some(c) = refer_to_component.get(&ident.to_string())
-            && let Some(TokenTree::Punct(p)) = iter.next()
-            && p.as_char() == '='
+    // 找出语句中被修改的变量，`touch!(a, b)` 显式地标记变量已被修改
+    let mut changed = Vec::new();
+    let is_tracked = |name: &String| {
+        refer_to_component.contains_key(name) || memos.iter().any(|m| m.inputs.contains(name))
+    };
+    for (index, tree) in stmt.iter().enumerate() {
+        let TokenTree::Ident(ident) = tree else {
+            continue;
+        };
+        let name = ident.to_string();
+        if name == "touch"
+            && is_punct(stmt.get(index + 1), '!', None)
+            && let Some(TokenTree::Group(g)) = stmt.get(index + 2)
         {
-            for (component_id, properties) in c.iter() {
-                for property_name in properties.iter() {
-                    stmts.push(ts!("this.{}.set_{}(&{});", component_id, property_name, i));
+            for i in g.stream() {
+                if let TokenTree::Ident(i) = i
+                    && is_tracked(&i.to_string())
+                {
+                    changed.retain(|(n, _)| *n != i.to_string());
+                    changed.push((i.to_string(), true));
                 }
-                stmts.push(ts!("this.{}.update();", component_id));
             }
-            break;
+        } else if is_tracked(&name)
+            && !changed.iter().any(|(n, _)| *n == name)
+            && is_changed(stmt, index)
+        {
+            changed.push((name, false));
+        }
+    }
+
+    // 重新计算依赖被修改变量的 memo，它们的变化继续传递给依赖它们的 memo 和子组件
+    let mut index = 0;
+    while let Some((name, is_touched)) = changed.get(index).cloned() {
+        for memo in memos.iter().filter(|m| m.inputs.contains(&name)) {
+            stmts.push(ts!("{} = {};", memo.name, memo.value(is_touched)));
+            match changed.iter_mut().find(|(n, _)| *n == memo.name) {
+                Some((_, t)) => *t |= is_touched,
+                None => changed.push((memo.name.clone(), is_touched)),
+            }
+        }
+        index += 1;
+    }
+
+    for (name, is_touched) in changed {
+        let Some(c) = refer_to_component.get(&name) else {
+            continue;
+        };
+        for (component_id, properties) in c.iter() {
+            // 所有属性都要设置，因此不能使用短路的 `||`；只有属性发生变化时才更新子组件，
+            // 而 `touch!` 总是更新子组件（例如内部可变的值与旧值总是相等）。
+            // 子组件在本轮轮询结束时才更新，同一轮中的多次修改只触发一次重新渲染
+            let mut setters = properties
+                .iter()
+                .map(|p| format!("c.set_{}(&{})", p, name))
+                .collect::<Vec<_>>();
+            if is_touched {
+                setters.push("true".to_owned());
+            }
+            let update = format!("if {} {{\nc.mark_dirty();\n}}", setters.join(" | "));
+            // 条件分支中的子组件只有在已经创建时才需要更新
+            if branches.is_conditional(component_id) {
+                stmts.push(ts!(
+                    "if let Some(c) = this.{}.borrow().clone() {{\n{}\n}}",
+                    component_id,
+                    update
+                ));
+            } else {
+                stmts.push(ts!("{{\nlet c = &this.{};\n{}\n}}", component_id, update));
+            }
         }
     }
     stmt.clear();
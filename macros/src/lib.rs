@@ -1,13 +1,13 @@
-mod arguments;
-mod block;
 #[macro_use]
 mod macros;
+mod arguments;
+mod block;
 mod utils;
 
 use {
     arguments::handle_arguments,
-    block::handle_block,
-    proc_macro::{TokenStream, TokenTree},
+    block::{Block, handle_block},
+    proc_macro::{Delimiter, TokenStream, TokenTree},
     utils::to_camel,
 };
 
@@ -50,19 +50,45 @@ pub fn component(attrs: TokenStream, item: TokenStream) -> TokenStream {
         property_field_initializers,
         property_field_getters_and_setters,
     ) = handle_arguments(g.stream(), &component_name);
-    let Some(TokenTree::Group(g)) = iter.next() else {
-        return error!(raw, "Expected function block");
+    // 可选的返回类型（`-> Result<(), E>`），一直读取到函数体为止
+    let mut return_type = Vec::new();
+    let g = loop {
+        match iter.next() {
+            Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Brace => break g,
+            Some(t) => return_type.push(t),
+            None => return error!(raw, "Expected function block"),
+        }
+    };
+    // 只有返回 `()` 以外的类型（`Result`）时函数体才可能失败，显式的 `-> ()` 与省略返回类型相同
+    let is_fallible = match return_type.get(2..) {
+        None | Some([]) => false,
+        Some([TokenTree::Group(g)]) => {
+            g.delimiter() != Delimiter::Parenthesis || !g.stream().is_empty()
+        }
+        _ => true,
     };
-    let (component_block, mut field_defines, mut field_initializers) =
-        handle_block(g.stream(), &mut component_var_bindings, &component_name);
+    let return_type = TokenStream::from_iter(return_type);
+    let Block {
+        body: component_block,
+        mut field_defines,
+        mut field_initializers,
+        hooks,
+        unmounts,
+    } = handle_block(g.stream(), &mut component_var_bindings, is_fallible);
     let component_name_camel = to_camel(&component_name);
+    let has_hook = |name: &str| hooks.iter().any(|(h, _)| h == name);
+    let tracks_mount = has_hook("on_mount") || has_hook("on_update");
+    if tracks_mount {
+        field_defines.push(ts!("_mounted: Cell<bool>,"));
+        field_initializers.push(ts!("_mounted: Default::default(),"));
+    }
     field_defines.extend_from_slice(&property_field_defines);
     let field_defines = field_defines
         .iter()
         .map(|i| i.to_string())
         .collect::<String>();
     let component_struct = ts!(
-        "{}\n{} struct {} <'a> {{\n_rt: Weak<Runtime<'a, ()>>,\n_cancellable: RefCell<Cancellable>,\n{}\n}}",
+        "{}\n{} struct {} <'a> {{\n_rt: Weak<Runtime<'a>>,\n_cancellable: RefCell<Cancellable>,\n{}\n}}",
         attrs,
         vis,
         component_name_camel,
@@ -75,38 +101,87 @@ pub fn component(attrs: TokenStream, item: TokenStream) -> TokenStream {
         .collect::<String>();
 
     let component_new = ts!(
-        "fn new(rt: Weak<Runtime<'a, ()>>) -> Self {{ Self {{\n{}\n_rt: rt,\n_cancellable: Default::default(),\n}} }}",
+        "fn new(rt: Weak<Runtime<'a>>) -> Self {{ Self {{\n{}\n_rt: rt,\n_cancellable: Default::default(),\n}} }}",
         field_initializers
     );
-    let component_get_rt = ts!("fn get_rt(&self) -> Weak<Runtime<'a, ()>> {{ self._rt.clone() }}");
-    let component_update = ts!(
-        "fn update(self: &Rc<Self>) {{\nlet mut cancellable = self._cancellable.borrow_mut();\ncancellable.cancel();\n*cancellable = self.spawn({}(Rc::downgrade(self)));\n}}",
+    let component_get_rt = ts!("fn get_rt(&self) -> Weak<Runtime<'a>> {{ self._rt.clone() }}");
+    let component_name_fn = ts!(
+        "fn name(&self) -> &'static str {{ \"{}\" }}",
+        component_name
+    );
+    let component_rerender = ts!(
+        "fn rerender(self: &Rc<Self>) {{\nlet mut cancellable = self._cancellable.borrow_mut();\ncancellable.cancel();\n*cancellable = self.render(self.observe({}(Rc::downgrade(self)))).into();\n}}",
         component_name
     );
+    let component_unmount = ts!(
+        "fn unmount(&self) {{\nself._cancellable.borrow().cancel();\n{}\n}}",
+        unmounts.iter().map(|i| i.to_string()).collect::<String>()
+    );
+
+    // 同一个钩子的多个代码块按声明顺序依次执行
+    let component_hooks = ["on_mount", "on_update", "on_unmount"]
+        .iter()
+        .filter(|name| has_hook(name))
+        .map(|name| {
+            let blocks = hooks
+                .iter()
+                .filter(|(h, _)| h == name)
+                .map(|(_, b)| format!("{{\n{}\n}}\n", b))
+                .collect::<String>();
+            let receiver = if *name == "on_unmount" {
+                "&self"
+            } else {
+                "self: &Rc<Self>"
+            };
+            format!(
+                "fn {}({}) {{\n#[allow(unused_variables)]\nlet this = self;\n{}}}\n",
+                name, receiver, blocks
+            )
+        })
+        .collect::<String>();
+    let component_is_mounted = if tracks_mount {
+        "fn is_mounted(&self) -> bool {\nself._mounted.get()\n}\n"
+    } else {
+        ""
+    };
+    let component_drop = if has_hook("on_unmount") {
+        format!(
+            "{}\nimpl<'a> Drop for {} <'a> {{\nfn drop(&mut self) {{\nself.on_unmount();\n}}\n}}",
+            attrs, component_name_camel
+        )
+    } else {
+        String::new()
+    };
 
     let component_field_getters_and_setters = property_field_getters_and_setters
         .iter()
         .map(|i| i.to_string())
         .collect::<String>();
     let component_impl = ts!(
-        "{}\nimpl<'a> Component<'a> for {} <'a> {{\n{}\n{}\n{}\n}}\n{}\nimpl<'a> {} <'a> {{\n{}\n}}",
+        "{}\nimpl<'a> Component<'a> for {} <'a> {{\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n}}\n{}\n{}\nimpl<'a> {} <'a> {{\n{}\n}}",
         attrs,
         component_name_camel,
         component_new,
         component_get_rt,
-        component_update,
+        component_name_fn,
+        component_rerender,
+        component_unmount,
+        component_is_mounted,
+        component_hooks,
+        component_drop,
         attrs,
         component_name_camel,
         component_field_getters_and_setters
     );
     ts!(
-        "{}\n{}\n{}\n{} async fn {}({}) {}",
+        "{}\n{}\n{}\n{} async fn {}({}) {} {}",
         component_struct,
         component_impl,
         attrs,
         vis,
         component_name,
         component_arguments,
+        return_type,
         component_block
     )
 }
@@ -26,7 +26,7 @@ pub fn take_while(iter: &mut IntoIter, end_token: &TokenTree) -> Result<TokenStr
     let mut found_end = false;
     let mut span = Span::call_site();
 
-    while let Some(token) = iter.next() {
+    for token in iter.by_ref() {
         span = token.span();
         if token.to_string() == end_token.to_string() {
             found_end = true;
@@ -39,5 +39,5 @@ pub fn take_while(iter: &mut IntoIter, end_token: &TokenTree) -> Result<TokenStr
         return Err(span);
     }
 
-    Ok(TokenStream::from_iter(tokens.into_iter()))
+    Ok(TokenStream::from_iter(tokens))
 }
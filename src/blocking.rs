@@ -0,0 +1,194 @@
+use {
+    crate::runtime::JoinError,
+    std::{
+        collections::VecDeque,
+        io::Result as IoResult,
+        panic::{AssertUnwindSafe, catch_unwind},
+        pin::Pin,
+        sync::{Arc, Condvar, Mutex, OnceLock},
+        task::{Context, Poll, Waker},
+        thread::{self, available_parallelism},
+        time::Duration,
+    },
+};
+
+/// A closure running on the blocking pool.
+type Job = Box<dyn FnOnce() + Send>;
+
+/// The result of a blocking closure and the waker of the task awaiting it.
+type BlockingSlot<T> = (Option<Result<T, JoinError>>, Option<Waker>);
+
+/// How long an idle worker thread waits for new work before exiting.
+const KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+/// A pool of worker threads shared by every runtime of the process.
+///
+/// Threads are started on demand, up to a limit, and exit after being idle for a while.
+struct Pool {
+    state: Mutex<PoolState>,
+    condvar: Condvar,
+    max_threads: usize,
+}
+
+#[derive(Default)]
+struct PoolState {
+    queue: VecDeque<Job>,
+    threads: usize,
+    idle: usize,
+}
+
+impl Pool {
+    fn get() -> &'static Self {
+        static POOL: OnceLock<Pool> = OnceLock::new();
+        POOL.get_or_init(|| Self {
+            state: Default::default(),
+            condvar: Condvar::new(),
+            max_threads: available_parallelism().map_or(4, |n| n.get() * 4),
+        })
+    }
+
+    /// Queues `job` for a worker thread, starting a new one if needed.
+    ///
+    /// Fails, without running `job`, if no worker thread exists and none can be started.
+    fn execute(&'static self, job: Job) -> IoResult<()> {
+        let mut state = self.state.lock().unwrap();
+        state.queue.push_back(job);
+        self.condvar.notify_one();
+        // 空闲线程不足以处理所有排队的任务时，启动新的线程
+        if state.queue.len() > state.idle && state.threads < self.max_threads {
+            state.threads += 1;
+            let spawned = thread::Builder::new()
+                .name("compo-blocking".into())
+                .spawn(move || self.work());
+            if let Err(e) = spawned {
+                // 无法启动线程时由已有的线程处理排队的任务；没有线程时任务永远不会执行
+                state.threads -= 1;
+                if state.threads == 0 {
+                    let job = state.queue.pop_back();
+                    // 释放锁后再销毁任务，其捕获的值可能再次调用 spawn_blocking
+                    drop(state);
+                    drop(job);
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn work(&self) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(job) = state.queue.pop_front() {
+                drop(state);
+                job();
+                state = self.state.lock().unwrap();
+                continue;
+            }
+
+            state.idle += 1;
+            let (next, timeout) = self.condvar.wait_timeout(state, KEEP_ALIVE).unwrap();
+            state = next;
+            state.idle -= 1;
+            if timeout.timed_out() && state.queue.is_empty() {
+                state.threads -= 1;
+                break;
+            }
+        }
+    }
+}
+
+/// A future resolving to the result of a closure run with `spawn_blocking`.
+///
+/// Dropping it does not stop the closure, its result is discarded instead.
+pub struct Blocking<T> {
+    slot: Arc<Mutex<BlockingSlot<T>>>,
+}
+
+impl<T> Future for Blocking<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.slot.lock().unwrap();
+        match slot.0.take() {
+            Some(output) => Poll::Ready(output),
+            None => {
+                slot.1.replace(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Runs `f` on a background thread pool, so blocking work does not stall other components.
+///
+/// The returned future resolves on the awaiting thread once `f` returns; its waker wakes the
+/// event loop, so no polling is needed in the meantime. If `f` panics, the future resolves to
+/// `JoinError::Panicked`; if no pool thread can be started to run it, to `JoinError::Cancelled`.
+///
+/// # Examples
+///
+/// ```
+/// use compo::prelude::*;
+///
+/// async fn example() {
+///     let sum = spawn_blocking(|| (0..1_000_000u64).sum::<u64>()).await;
+///     assert_eq!(sum.ok(), Some(499_999_500_000));
+/// }
+/// ```
+pub fn spawn_blocking<F, T>(f: F) -> Blocking<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let slot = Arc::new(Mutex::new(BlockingSlot::default()));
+    let slot2 = slot.clone();
+    let executed = Pool::get().execute(Box::new(move || {
+        let output = catch_unwind(AssertUnwindSafe(f)).map_err(|_| JoinError::Panicked);
+        let waker = {
+            let mut slot = slot2.lock().unwrap();
+            slot.0 = Some(output);
+            slot.1.take()
+        };
+        // 释放锁后再唤醒等待的任务
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }));
+    if executed.is_err() {
+        slot.lock().unwrap().0 = Some(Err(JoinError::Cancelled));
+    }
+
+    Blocking { slot }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        crate::{blocking::spawn_blocking, runtime::JoinError, runtime::Runtime},
+        std::{cell::RefCell, rc::Rc, thread, time::Duration},
+    };
+
+    #[test]
+    fn test_spawn_blocking_resumes_task() {
+        let rt = Runtime::new().unwrap();
+        let results = Rc::new(RefCell::new(Vec::new()));
+        let results2 = results.clone();
+        rt.spawn(async move {
+            let output = spawn_blocking(|| {
+                thread::sleep(Duration::from_millis(20));
+                thread::current().name().map(str::to_owned)
+            })
+            .await;
+            results2.borrow_mut().push(output.map(|n| n.is_some()));
+            let output = spawn_blocking(|| panic!("boom")).await;
+            results2.borrow_mut().push(output.map(|()| true));
+        });
+        rt.run_until_done();
+
+        assert!(matches!(
+            results.borrow().as_slice(),
+            [Ok(true), Err(JoinError::Panicked)]
+        ));
+    }
+}
@@ -1,23 +1,658 @@
 use {
-    crate::runtime::{Cancellable, Runtime},
-    std::rc::{Rc, Weak},
+    crate::{
+        blocking::{Blocking, spawn_blocking},
+        failure::Failure,
+        reactive::{Effect, ReactionKey, Tracked},
+        runtime::{JoinHandle, Runtime, Shutdown},
+    },
+    std::{
+        any::type_name,
+        error::Error,
+        rc::{Rc, Weak},
+    },
 };
 
+/// The output of a component body: `()` for infallible components, or `Result<(), E>`
+/// for fallible ones.
+pub trait ComponentOutput {
+    /// Converts the output into a result, boxing the error.
+    fn into_result(self) -> Result<(), Box<dyn Error>>;
+}
+
+impl ComponentOutput for () {
+    fn into_result(self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+impl<E> ComponentOutput for Result<(), E>
+where
+    E: Into<Box<dyn Error>>,
+{
+    fn into_result(self) -> Result<(), Box<dyn Error>> {
+        self.map_err(Into::into)
+    }
+}
+
+/// Marks variables of a component body as changed, so the child components rendered with them
+/// are updated.
+///
+/// Assignments (`x = ..`, `x += ..`, `x.field = ..`, `x[i] = ..`) and common mutating methods
+/// such as `x.push(..)` are detected by `#[component]`; `touch!` covers the other changes, e.g.
+/// through a `&mut` reference or a method of your own.
+///
+/// ```
+/// use compo::prelude::*;
+///
+/// #[component]
+/// async fn list() {
+///     let mut count = 0;
+///     #[render]
+///     counter { count: count };
+///     let r = &mut count;
+///     *r = 2;
+///     touch!(count);
+/// }
+///
+/// #[component]
+/// async fn counter(count: u32) {
+///     println!("{}", count);
+/// }
+/// ```
+#[macro_export]
+macro_rules! touch {
+    ($($var:ident),+ $(,)?) => {
+        $(let _ = &$var;)+
+    };
+}
+
 pub trait Component<'a> {
-    fn new(rt: Weak<Runtime<'a, ()>>) -> Self;
+    fn new(rt: Weak<Runtime<'a>>) -> Self;
 
-    fn get_rt(&self) -> Weak<Runtime<'a, ()>>;
+    fn get_rt(&self) -> Weak<Runtime<'a>>;
 
-    fn spawn<Fut>(&self, fut: Fut) -> Cancellable
+    /// Returns the name of the component, used when reporting failures of its tasks.
+    fn name(&self) -> &'static str {
+        type_name::<Self>()
+    }
+
+    fn spawn<Fut>(&self, fut: Fut) -> JoinHandle<Fut::Output>
+    where
+        Fut: Future + 'a,
+        Fut::Output: 'a,
+    {
+        if let Some(rt) = self.get_rt().upgrade() {
+            rt.spawn_named(self.name(), fut)
+        } else {
+            Default::default()
+        }
+    }
+
+    /// Spawns a background task that does not keep the event loop alive,
+    /// see `Runtime::spawn_daemon`.
+    fn spawn_daemon<Fut>(&self, fut: Fut) -> JoinHandle<Fut::Output>
     where
-        Fut: Future<Output=()> + 'a,
+        Fut: Future + 'a,
+        Fut::Output: 'a,
     {
         if let Some(rt) = self.get_rt().upgrade() {
-            rt.spawn(fut)
+            rt.spawn_task(Some(self.name()), true, fut)
         } else {
             Default::default()
         }
     }
 
-    fn update(self: &Rc<Self>);
+    /// Runs `f` on the blocking thread pool, see `spawn_blocking`. The returned future
+    /// resolves once `f` returns, resuming the awaiting component body.
+    fn spawn_blocking<F, T>(&self, f: F) -> Blocking<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        spawn_blocking(f)
+    }
+
+    /// Returns a future that resolves when the loop begins shutting down, so the component
+    /// can clean up before the loop exits. See `Runtime::on_shutdown`.
+    fn on_shutdown(&self) -> Shutdown<'a> {
+        match self.get_rt().upgrade() {
+            Some(rt) => rt.on_shutdown(),
+            None => Shutdown::default(),
+        }
+    }
+
+    /// Spawns a run of a component body. An error returned by the body is reported to the
+    /// nearest error boundary, or to the failure handler of the runtime.
+    fn render<Fut>(&self, fut: Fut) -> JoinHandle<()>
+    where
+        Fut: Future + 'a,
+        Fut::Output: ComponentOutput,
+    {
+        let name = self.name();
+        let rt = self.get_rt();
+        self.spawn(async move {
+            if let Err(e) = fut.await.into_result()
+                && let Some(rt) = rt.upgrade()
+            {
+                rt.report(Failure::from_error(Some(name), e));
+            }
+        })
+    }
+
+    /// Runs the component body again, e.g. after one of its properties changed, calling the
+    /// `on_update` hook first if the component is mounted. Inside `batch`, the update is
+    /// deferred until the batch ends.
+    fn update(self: &Rc<Self>)
+    where
+        Self: 'a,
+    {
+        if let Some(rt) = self.get_rt().upgrade()
+            && rt.is_batching()
+        {
+            self.mark_dirty();
+            return;
+        }
+
+        // 组件第一次渲染之前的更新不调用 on_update，保证 on_mount 总是先被调用
+        if self.is_mounted() {
+            self.on_update();
+        }
+        self.rerender();
+    }
+
+    /// Schedules an `update` at the end of the current tick, so several changes during the
+    /// tick re-render the component only once. `#[component]` uses it when a property of a
+    /// child changes.
+    fn mark_dirty(self: &Rc<Self>)
+    where
+        Self: 'a,
+    {
+        if let Some(rt) = self.get_rt().upgrade() {
+            let this = Rc::downgrade(self);
+            rt.schedule_update(
+                Rc::as_ptr(self) as *const () as usize,
+                Box::new(move || {
+                    if let Some(this) = this.upgrade() {
+                        this.update();
+                    }
+                }),
+            );
+        }
+    }
+
+    /// Wraps a run of the component body, so the component is updated when a `Signal` or
+    /// `Memo` read by the run changes. Replaces the signals recorded for the previous run.
+    fn observe<Fut>(self: &Rc<Self>, fut: Fut) -> impl Future<Output = Fut::Output> + 'a
+    where
+        Self: 'a,
+        Fut: Future + 'a,
+    {
+        let observer = self.get_rt().upgrade().map(|rt| {
+            let this = Rc::downgrade(self);
+            rt.reactions.observe(
+                ReactionKey::Component(Rc::as_ptr(self) as *const () as usize),
+                Rc::new(move || match this.upgrade() {
+                    Some(this) => {
+                        this.mark_dirty();
+                        true
+                    }
+                    None => false,
+                }),
+            )
+        });
+        Tracked {
+            observer,
+            future: Box::pin(fut),
+        }
+    }
+
+    /// Creates an effect that reruns whenever a signal it read changes, see `Runtime::effect`.
+    fn effect(&self, f: impl Fn() + 'a) -> Effect<'a> {
+        match self.get_rt().upgrade() {
+            Some(rt) => rt.effect(f),
+            None => Effect::detached(),
+        }
+    }
+
+    /// Runs `f`, deferring component updates until it returns, see `Runtime::batch`.
+    fn batch<R>(&self, f: impl FnOnce() -> R) -> R {
+        match self.get_rt().upgrade() {
+            Some(rt) => rt.batch(f),
+            None => f(),
+        }
+    }
+
+    /// Runs the component body again, cancelling its previous run. Parents render their
+    /// children with it; unlike `update` it does not call the `on_update` hook.
+    fn rerender(self: &Rc<Self>);
+
+    /// Cancels the running body of the component and unmounts its children, so the component
+    /// is dropped together with its tasks. Parents call it when a conditional child is no
+    /// longer rendered.
+    fn unmount(&self) {}
+
+    /// Returns `true` once the component body has started its first run. `#[component]`
+    /// tracks it for components declaring `on_mount` or `on_update` hooks.
+    fn is_mounted(&self) -> bool {
+        true
+    }
+
+    /// Called before the first run of the component body. A component declares it with an
+    /// `#[on_mount] { ... }` block.
+    fn on_mount(self: &Rc<Self>) {}
+
+    /// Called by `update` before the component body runs again, once the component is
+    /// mounted. A component declares it with an `#[on_update] { ... }` block.
+    fn on_update(self: &Rc<Self>) {}
+
+    /// Called when the component is dropped, e.g. when its parent is dropped. A component
+    /// declares it with an `#[on_unmount] { ... }` block.
+    fn on_unmount(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use {crate::prelude::*, std::cell::RefCell};
+
+    thread_local! {
+        static EVENTS: RefCell<Vec<String>> = Default::default();
+    }
+
+    fn log(event: String) {
+        EVENTS.with_borrow_mut(|e| e.push(event));
+    }
+
+    #[component]
+    async fn probe(value: u32) {
+        #[on_mount]
+        {
+            log(format!("mount {}", this.get_value()));
+        }
+        #[on_update]
+        {
+            log(format!("update {}", this.get_value()));
+        }
+        #[on_unmount]
+        {
+            log(format!("unmount {}", this.get_value()));
+        }
+        log(format!("render {}", value));
+    }
+
+    #[component]
+    async fn waiter(name: char) {
+        #[on_unmount]
+        {
+            log(format!("unmount {}", this.get_name()));
+        }
+        log(format!("wait {}", name));
+        sleep(Duration::from_secs(3600)).await;
+    }
+
+    #[component]
+    async fn toggle(show: bool) {
+        if *show {
+            #[render]
+            waiter { name: 'a' };
+        } else {
+            #[render]
+            waiter { name: 'b' };
+        }
+    }
+
+    #[test]
+    fn test_conditional_children_are_unmounted() {
+        let rt = TestRuntime::new();
+        let c = rt.mount(toggle);
+        rt.run_until_stalled();
+        c.set_show(&true);
+        c.update();
+        rt.run_until_stalled();
+        c.set_show(&false);
+        c.update();
+        rt.run_until_stalled();
+
+        EVENTS.with_borrow(|e| {
+            assert_eq!(*e, ["wait b", "unmount b", "wait a", "unmount a", "wait b"])
+        });
+    }
+
+    #[component]
+    async fn optional(show: bool) {
+        if *show {
+            #[render]
+            waiter { name: 'a' };
+        }
+        let label = if !*show { 'b' } else { 'c' };
+        log(format!("label {}", label));
+    }
+
+    #[test]
+    fn test_conditional_children_without_else_are_unmounted() {
+        let rt = TestRuntime::new();
+        let c = rt.mount(optional);
+        c.set_show(&true);
+        rt.run_until_stalled();
+        c.set_show(&false);
+        c.update();
+        rt.run_until_stalled();
+
+        // 后面无关的 `if`/`else` 表达式不会卸载前面条件分支中的子组件
+        EVENTS.with_borrow(|e| assert_eq!(*e, ["label c", "wait a", "label b", "unmount a"]));
+        assert_eq!(rt.runtime().count(), 0);
+    }
+
+    #[component]
+    async fn selector(choice: u32) {
+        match *choice {
+            0 => {
+                #[render]
+                waiter { name: 'a' };
+            }
+            _ => log(format!("choice {}", choice)),
+        }
+    }
+
+    #[test]
+    fn test_children_of_block_arms_are_unmounted_by_expression_arms() {
+        let rt = TestRuntime::new();
+        let c = rt.mount(selector);
+        rt.run_until_stalled();
+        c.set_choice(&1);
+        c.update();
+        rt.run_until_stalled();
+
+        EVENTS.with_borrow(|e| assert_eq!(*e, ["wait a", "choice 1", "unmount a"]));
+        assert_eq!(rt.runtime().count(), 0);
+    }
+
+    #[component]
+    async fn item(id: u32) {
+        #[on_mount]
+        {
+            log(format!("mount {}", this.get_id()));
+        }
+        #[on_unmount]
+        {
+            log(format!("unmount {}", this.get_id()));
+        }
+    }
+
+    #[component]
+    async fn list(start: u32, end: u32) {
+        for i in *start..*end {
+            #[render(key = i)]
+            item { id: i };
+        }
+    }
+
+    #[test]
+    fn test_keyed_children_are_reused() {
+        let rt = TestRuntime::new();
+        let c = rt.mount(list);
+        c.set_end(&3);
+        rt.run_until_stalled();
+        c.set_start(&1);
+        c.set_end(&4);
+        c.update();
+        rt.run_until_stalled();
+
+        EVENTS.with_borrow(|e| {
+            assert_eq!(
+                *e,
+                ["mount 0", "mount 1", "mount 2", "unmount 0", "mount 3"]
+            )
+        });
+    }
+
+    #[component]
+    async fn evens(items: Vec<u32>) {
+        for i in items.iter() {
+            if i % 2 == 0 {
+                #[render(key = *i)]
+                item { id: *i };
+            }
+        }
+    }
+
+    #[test]
+    fn test_keyed_children_in_conditional_branches() {
+        let rt = TestRuntime::new();
+        let c = rt.mount(evens);
+        c.set_items(&vec![2, 3]);
+        rt.run_until_stalled();
+        c.set_items(&vec![2, 3, 4]);
+        c.update();
+        rt.run_until_stalled();
+
+        // 循环中某一项的分支未生效时，不会卸载列表中的其他项
+        EVENTS.with_borrow(|e| assert_eq!(*e, ["mount 2", "mount 4"]));
+    }
+
+    #[component]
+    async fn counting() {
+        let mut n = 0;
+        #[render]
+        probe { value: n };
+        n += 1;
+        let _ = n == 1;
+        touch!(n);
+    }
+
+    #[test]
+    fn test_changes_update_children() {
+        let rt = TestRuntime::new();
+        let _c = rt.mount(counting);
+        rt.run_until_stalled();
+
+        EVENTS.with_borrow(|e| assert_eq!(*e, ["mount 1", "render 1"]));
+    }
+
+    #[component]
+    async fn assigning(next: u32) {
+        let mut n = 0;
+        #[render]
+        probe { value: n };
+        sleep(Duration::from_millis(10)).await;
+        n = *next;
+    }
+
+    #[test]
+    fn test_equal_values_skip_child_updates() {
+        let rt = TestRuntime::new();
+        let _c = rt.mount(assigning);
+        rt.advance(Duration::from_millis(10));
+
+        EVENTS.with_borrow(|e| assert_eq!(*e, ["mount 0", "render 0"]));
+    }
+
+    #[test]
+    fn test_changed_values_update_children() {
+        let rt = TestRuntime::new();
+        let c = rt.mount(assigning);
+        c.set_next(&1);
+        rt.advance(Duration::from_millis(10));
+
+        EVENTS.with_borrow(|e| assert_eq!(*e, ["mount 0", "render 0", "update 1", "render 1"]));
+    }
+
+    /// A property type without `PartialEq`.
+    #[derive(Clone, Default)]
+    struct Token(u32);
+
+    #[component]
+    async fn opaque(#[no_eq] value: Token) {
+        log(format!("opaque {}", value.0));
+    }
+
+    #[component]
+    async fn opaque_parent() {
+        let mut value = Token(0);
+        #[render]
+        opaque { value: value };
+        sleep(Duration::from_millis(10)).await;
+        value = Token(0);
+    }
+
+    #[test]
+    fn test_no_eq_props_always_update() {
+        let rt = TestRuntime::new();
+        let _c = rt.mount(opaque_parent);
+        rt.advance(Duration::from_millis(10));
+
+        EVENTS.with_borrow(|e| assert_eq!(*e, ["opaque 0", "opaque 0"]));
+    }
+
+    #[component]
+    async fn label(text: String, lines: Vec<String>) {
+        log(format!("{} {:?}", text, lines));
+    }
+
+    #[component]
+    async fn labels() {
+        let mut text = String::from("a");
+        let lines = vec![String::from("b")];
+        #[render]
+        label {
+            text: text,
+            #[owned]
+            lines: lines,
+        };
+        text.push('c');
+    }
+
+    #[test]
+    fn test_clone_and_owned_props() {
+        let rt = TestRuntime::new();
+        let _c = rt.mount(labels);
+        rt.run_until_stalled();
+
+        EVENTS.with_borrow(|e| assert_eq!(*e, ["ac [\"b\"]"]));
+    }
+
+    #[component]
+    async fn pager(page: u32) {
+        #[memo]
+        let text = {
+            log(format!("compute {}", page));
+            page.to_string()
+        };
+        #[render]
+        label { text: text };
+    }
+
+    #[component]
+    async fn steps() {
+        let mut step = 1;
+        #[memo]
+        let text = format!("step {}", step);
+        #[render]
+        label { text: text };
+        step += 1;
+    }
+
+    #[test]
+    fn test_memo_values() {
+        let rt = TestRuntime::new();
+        let c = rt.mount(pager);
+        rt.run_until_stalled();
+        c.update();
+        rt.run_until_stalled();
+        let _c = rt.mount(steps);
+        rt.run_until_stalled();
+
+        EVENTS.with_borrow(|e| assert_eq!(*e, ["compute 0", "0 []", "0 []", "step 2 []"]));
+    }
+
+    #[component]
+    async fn mask(a: u32, b: u32) {
+        #[memo]
+        let bits = a | b;
+        log(format!("bits {}", bits));
+    }
+
+    #[test]
+    fn test_memo_inputs_of_bitwise_or() {
+        let rt = TestRuntime::new();
+        let c = rt.mount(mask);
+        c.set_a(&1);
+        rt.run_until_stalled();
+        c.set_b(&2);
+        c.update();
+        rt.run_until_stalled();
+
+        // `|` 是按位或运算符时，它两边的变量都是输入
+        EVENTS.with_borrow(|e| assert_eq!(*e, ["bits 1", "bits 3"]));
+    }
+
+    #[test]
+    fn test_batch_coalesces_updates() {
+        let rt = TestRuntime::new();
+        let c = rt.mount(probe);
+        rt.run_until_stalled();
+        c.batch(|| {
+            c.set_value(&1);
+            c.update();
+            c.set_value(&2);
+            c.update();
+        });
+        rt.run_until_stalled();
+
+        EVENTS.with_borrow(|e| assert_eq!(*e, ["mount 0", "render 0", "update 2", "render 2"]));
+    }
+
+    #[component]
+    async fn reader(count: Signal<u32>) {
+        log(format!("count {}", count.get()));
+    }
+
+    #[test]
+    fn test_signals_update_readers() {
+        let rt = TestRuntime::new();
+        let count = Signal::new(0);
+        let c = rt.mount(reader);
+        c.set_count(&count);
+        rt.run_until_stalled();
+        let increment = || count.update(|c| *c += 1);
+        increment();
+        increment();
+        rt.run_until_stalled();
+        drop(c);
+        increment();
+        rt.run_until_stalled();
+
+        EVENTS.with_borrow(|e| assert_eq!(*e, ["count 0", "count 2"]));
+    }
+
+    #[component]
+    async fn unit() -> () {
+        log("unit".to_owned());
+    }
+
+    #[test]
+    fn test_unit_return_type_is_infallible() {
+        let rt = TestRuntime::new();
+        let _c = rt.mount(unit);
+        rt.run_until_stalled();
+
+        EVENTS.with_borrow(|e| assert_eq!(*e, ["unit"]));
+    }
+
+    #[test]
+    fn test_lifecycle_hooks() {
+        let rt = TestRuntime::new();
+        let c = rt.mount(probe);
+        rt.run_until_stalled();
+        c.set_value(&1);
+        c.update();
+        rt.run_until_stalled();
+        drop(c);
+
+        EVENTS.with_borrow(|e| {
+            assert_eq!(
+                *e,
+                ["mount 0", "render 0", "update 1", "render 1", "unmount 1"]
+            )
+        });
+    }
 }
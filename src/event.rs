@@ -3,12 +3,14 @@ use std::{
     cell::UnsafeCell,
     error::Error,
     fmt::{Debug, Display, Formatter, Result as FmtResult},
-    mem::transmute,
     pin::Pin,
     rc::{Rc, Weak},
     task::{Context, Poll, Waker},
 };
 
+/// A pending receive slot: the delivered value and the waker of the listening task.
+type Slot<'a, T> = (Option<Cow<'a, T>>, Option<Waker>);
+
 /// Error returned when an event emission fails.
 ///
 /// This error occurs in two scenarios:
@@ -36,7 +38,7 @@ pub struct EventEmitter<'a, T>
 where
     T: Clone,
 {
-    receivers: Weak<UnsafeCell<Vec<&'a mut (Option<Cow<'a, T>>, Option<Waker>)>>>,
+    receivers: Weak<UnsafeCell<Vec<&'a mut Slot<'a, T>>>>,
 }
 
 impl<'a, T> Default for EventEmitter<'a, T>
@@ -70,8 +72,7 @@ where
             return Err(EventEmitError(value));
         };
 
-        let receivers: &mut Vec<&'a mut (Option<Cow<'a, T>>, Option<Waker>)> =
-            unsafe { transmute(receivers.get()) };
+        let receivers: &mut Vec<&'a mut Slot<'a, T>> = unsafe { &mut *receivers.get() };
         if receivers.is_empty() {
             return Err(EventEmitError(value));
         }
@@ -101,8 +102,8 @@ pub struct EventListener<'a, T>
 where
     T: Clone,
 {
-    receivers: Rc<UnsafeCell<Vec<&'a mut (Option<Cow<'a, T>>, Option<Waker>)>>>,
-    value: UnsafeCell<(Option<Cow<'a, T>>, Option<Waker>)>,
+    receivers: Rc<UnsafeCell<Vec<&'a mut Slot<'a, T>>>>,
+    value: UnsafeCell<Slot<'a, T>>,
 }
 
 impl<'a, T> Clone for EventListener<'a, T>
@@ -149,10 +150,9 @@ where
     /// This method must be called before any `emit()` calls for the emitter to successfully
     /// broadcast events. If all listeners are destroyed, subsequent `emit()` calls will fail.
     pub fn listen(&self) -> RecvFuture<'a, T> {
-        let receivers: &mut Vec<&'a mut (Option<Cow<'a, T>>, Option<Waker>)> =
-            unsafe { transmute(self.receivers.get()) };
-        let value = unsafe { transmute(self.value.get()) };
-        let value2 = unsafe { transmute(self.value.get()) };
+        let receivers: &mut Vec<&'a mut Slot<'a, T>> = unsafe { &mut *self.receivers.get() };
+        let value = unsafe { &mut *self.value.get() };
+        let value2 = unsafe { &mut *self.value.get() };
         receivers.push(value2);
         RecvFuture { value }
     }
@@ -166,7 +166,7 @@ pub struct RecvFuture<'a, T>
 where
     T: Clone,
 {
-    value: &'a mut (Option<Cow<'a, T>>, Option<Waker>),
+    value: &'a mut Slot<'a, T>,
 }
 
 /// Implementation of the `Future` trait for `RecvFuture`.
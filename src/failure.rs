@@ -0,0 +1,101 @@
+use std::{
+    any::Any,
+    error::Error,
+    fmt::{Debug, Display, Formatter, Result as FmtResult},
+};
+
+/// The reason a task failed.
+pub enum FailureCause {
+    /// The task panicked; contains the panic payload.
+    Panic(Box<dyn Any + Send>),
+    /// A fallible component body returned an error.
+    Error(Box<dyn Error>),
+}
+
+/// A failure of a task, reported to the nearest error boundary or the failure handler of the runtime.
+///
+/// Carries the name of the component owning the task (if the task was spawned by a component)
+/// and the cause of the failure.
+pub struct Failure {
+    name: Option<&'static str>,
+    cause: FailureCause,
+}
+
+impl Failure {
+    pub(crate) fn panic(name: Option<&'static str>, payload: Box<dyn Any + Send>) -> Self {
+        Self {
+            name,
+            cause: FailureCause::Panic(payload),
+        }
+    }
+
+    pub(crate) fn from_error(name: Option<&'static str>, error: Box<dyn Error>) -> Self {
+        Self {
+            name,
+            cause: FailureCause::Error(error),
+        }
+    }
+
+    /// Returns the name of the component that owns the failed task.
+    pub fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    /// Returns the cause of the failure.
+    pub fn cause(&self) -> &FailureCause {
+        &self.cause
+    }
+
+    /// Consumes the failure and returns its cause, e.g. to resume a panic.
+    pub fn into_cause(self) -> FailureCause {
+        self.cause
+    }
+
+    /// Returns the panic message, if the task panicked with a string payload.
+    pub fn message(&self) -> Option<&str> {
+        match &self.cause {
+            FailureCause::Panic(payload) => payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str)),
+            FailureCause::Error(_) => None,
+        }
+    }
+
+    /// Returns the error, if a fallible component body returned one.
+    pub fn error(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.cause {
+            FailureCause::Error(e) => Some(e.as_ref()),
+            FailureCause::Panic(_) => None,
+        }
+    }
+}
+
+impl Error for Failure {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.error()
+    }
+}
+
+impl Debug for Failure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let mut f = f.debug_struct("Failure");
+        f.field("name", &self.name);
+        match &self.cause {
+            FailureCause::Panic(_) => f.field("message", &self.message()),
+            FailureCause::Error(e) => f.field("error", e),
+        };
+        f.finish()
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let name = self.name.unwrap_or("<unnamed>");
+        match (&self.cause, self.message()) {
+            (FailureCause::Error(e), _) => write!(f, "{} failed: {}", name, e),
+            (_, Some(message)) => write!(f, "{} panicked: {}", name, message),
+            (_, None) => write!(f, "{} panicked", name),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use {
+    crate::component::Component,
+    std::{any::Any, collections::VecDeque, mem::take, rc::Rc},
+};
+
+/// The children rendered by a keyed `#[render(key = ...)]` inside a loop.
+///
+/// Each pass of the loop looks the children up by key: a child whose key was rendered in the
+/// previous pass is reused with its state, a new key creates a new child, and the children
+/// whose keys are not rendered anymore are unmounted when the loop ends. The children are kept
+/// in the order of the last pass.
+pub struct Keyed<C> {
+    children: VecDeque<(Box<dyn Any>, Rc<C>)>,
+    rendered: Vec<(Box<dyn Any>, Rc<C>)>,
+}
+
+impl<C> Default for Keyed<C> {
+    fn default() -> Self {
+        Self {
+            children: Default::default(),
+            rendered: Default::default(),
+        }
+    }
+}
+
+impl<C> Keyed<C> {
+    /// Starts a pass of the loop.
+    pub fn begin(&mut self) {
+        // 上一轮没有正常结束（例如循环被提前中断）时，保留它已经渲染的子组件
+        for child in take(&mut self.rendered).into_iter().rev() {
+            self.children.push_front(child);
+        }
+    }
+
+    /// Returns the child rendered for `key` in this pass, reusing the child rendered for the
+    /// same key in the previous pass, or creating one with `create`.
+    pub fn get_or_insert<K>(&mut self, key: K, create: impl FnOnce() -> Rc<C>) -> Rc<C>
+    where
+        K: PartialEq + 'static,
+    {
+        let is_key = |k: &Box<dyn Any>| k.downcast_ref::<K>() == Some(&key);
+        if let Some((_, child)) = self.rendered.iter().find(|(k, _)| is_key(k)) {
+            return child.clone();
+        }
+        // 顺序不变时要找的子组件总是在最前面
+        let index = self.children.iter().position(|(k, _)| is_key(k));
+        let child = match index.and_then(|i| self.children.remove(i)) {
+            Some((_, child)) => child,
+            None => create(),
+        };
+        self.rendered.push((Box::new(key), child.clone()));
+
+        child
+    }
+
+    /// Returns the number of children.
+    pub fn len(&self) -> usize {
+        self.children.len() + self.rendered.len()
+    }
+
+    /// Checks if there are no children.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Ends a pass of the loop, unmounting the children whose keys were not rendered.
+    pub fn finish<'a>(&mut self)
+    where
+        C: Component<'a>,
+    {
+        for (_, child) in take(&mut self.children) {
+            child.unmount();
+        }
+        self.children = take(&mut self.rendered).into();
+    }
+
+    /// Unmounts every child.
+    pub fn clear<'a>(&mut self)
+    where
+        C: Component<'a>,
+    {
+        self.begin();
+        self.finish();
+    }
+}
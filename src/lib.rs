@@ -1,6 +1,19 @@
+mod blocking;
 mod component;
 mod event;
+mod failure;
+mod keyed;
 mod r#loop;
+mod memoized;
+#[cfg(target_os = "linux")]
+pub mod net;
 pub mod prelude;
+mod proxy;
+mod reactive;
+#[cfg(target_os = "linux")]
+mod reactor;
 mod runtime;
+#[cfg(target_os = "linux")]
+mod signal;
+mod testing;
 mod time;
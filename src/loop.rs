@@ -1,76 +1,430 @@
+#[cfg(target_os = "linux")]
 use {
-    crate::{component::Component, prelude::Runtime},
+    crate::signal::{SignalKind, SignalListener},
+    std::{cell::RefCell, io::Error, mem::take},
+};
+use {
+    crate::{
+        component::{Component, ComponentOutput},
+        failure::Failure,
+        prelude::Runtime,
+        proxy::LoopProxy,
+        runtime::Scheduler,
+    },
     std::{
         cell::UnsafeCell,
-        mem::transmute,
         rc::{Rc, Weak},
+        sync::Arc,
+        time::{Duration, Instant},
     },
 };
 
+/// A handler invoked on each iteration of the event loop.
+type PollHandler<T> = Box<dyn Fn(&Loop<T>)>;
+
+/// A handler invoked when the loop receives a signal.
+#[cfg(target_os = "linux")]
+type SignalHandler<T> = Box<dyn Fn(&Loop<T>)>;
+
 /// The main event loop structure for managing component execution and event handling.
-pub struct Loop {
+///
+/// `T` is the type of the value the loop can be quit with, returned by `run`.
+pub struct Loop<T = ()> {
     quit_flag: UnsafeCell<bool>,
-    handlers: UnsafeCell<Vec<Box<dyn Fn(&Self)>>>,
+    quit_value: UnsafeCell<Option<T>>,
+    handlers: UnsafeCell<Vec<PollHandler<T>>>,
+    poll_interval: Duration,
+    failure_handler: Option<Box<dyn Fn(Failure)>>,
+    catch_panics: bool,
+    exit_when_done: bool,
+    shutdown_timeout: Duration,
+    scheduler: Arc<Scheduler>,
+    #[cfg(target_os = "linux")]
+    signal_handlers: Vec<(SignalKind, SignalHandler<T>)>,
+    #[cfg(target_os = "linux")]
+    handle_interrupt: bool,
 }
 
 impl Loop {
     /// Creates a new instance of the event loop.
+    ///
+    /// Use `Loop::<T>::default()` to create a loop that can be quit with a value of type `T`.
     pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<T> Default for Loop<T> {
+    fn default() -> Self {
         Self {
             quit_flag: false.into(),
+            quit_value: None.into(),
             handlers: Default::default(),
+            poll_interval: Duration::from_millis(10),
+            failure_handler: None,
+            catch_panics: false,
+            exit_when_done: false,
+            shutdown_timeout: Duration::from_secs(5),
+            scheduler: Arc::new(Scheduler::new()),
+            #[cfg(target_os = "linux")]
+            signal_handlers: Default::default(),
+            #[cfg(target_os = "linux")]
+            handle_interrupt: true,
         }
     }
+}
 
+impl<T> Loop<T> {
     /// Signals the event loop to stop running.
-    /// Once called, the loop will exit after completing the current iteration.
+    ///
+    /// Once called, poll handlers are no longer called and the runtime begins shutting down:
+    /// tasks awaiting `on_shutdown` are woken, and the loop exits once they have completed or
+    /// the shutdown timeout has passed.
     pub fn quit(&self) {
         unsafe { *self.quit_flag.get() = true };
     }
 
+    /// Signals the event loop to stop running, making `run` return `Some(value)`.
+    /// If called more than once before the loop exits, the last value wins.
+    pub fn quit_with(&self, value: T) {
+        unsafe { *self.quit_value.get() = Some(value) };
+        self.quit();
+    }
+
+    /// Returns a proxy for posting work into the loop, or waking or quitting it, from other
+    /// threads. Work posted before `run` is called is delivered once the loop starts.
+    pub fn proxy(&self) -> LoopProxy {
+        LoopProxy::new(self.scheduler.clone())
+    }
+
+    /// Sets how long the loop may stay idle before calling the poll handlers again.
+    /// Only applies when at least one poll handler is registered; defaults to 10 milliseconds.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Sets the handler receiving failures of components that are not caught by an error
+    /// boundary, such as errors returned by fallible component bodies. See `Runtime::on_failure`.
+    pub fn on_failure<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(Failure) + 'static,
+    {
+        self.failure_handler = Some(Box::new(handler));
+        self
+    }
+
+    /// Isolates panics of individual tasks instead of letting them unwind through the loop.
+    /// A panicking task is removed and `handler` receives the failure, including the name
+    /// of the component that owns the task. See `Runtime::catch_panics`.
+    pub fn catch_panics<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(Failure) + 'static,
+    {
+        self.failure_handler = Some(Box::new(handler));
+        self.catch_panics = true;
+        self
+    }
+
+    /// Makes the loop exit on its own once every task has completed, ignoring daemon tasks
+    /// (see `Runtime::spawn_daemon`). Without it, the loop runs until `quit` is called.
+    pub fn exit_when_done(mut self) -> Self {
+        self.exit_when_done = true;
+        self
+    }
+
+    /// Sets how long the loop waits after `quit` for components awaiting `on_shutdown` to finish
+    /// cleaning up, before exiting anyway. Defaults to 5 seconds.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
     /// Registers a handler function to be called on each iteration of the event loop.
-    /// The handler will be invoked before checking the quit flag.
+    /// While idle, the loop wakes up at least once per poll interval to call the handlers.
     pub fn register_poll_handler<F>(self, handler: F) -> Self
     where
         F: Fn(&Self) + 'static,
     {
-        let handlers: &mut Vec<Box<dyn Fn(&Self)>> = unsafe { transmute(self.handlers.get()) };
+        let handlers = unsafe { &mut *self.handlers.get() };
         handlers.push(Box::new(handler) as _);
         self
     }
 
+    /// Registers a handler function to be called on the loop thread whenever the process
+    /// receives the signal `kind`.
+    ///
+    /// The loop only installs signal handlers for the signals passed to `on_signal` (and
+    /// `SIGINT` unless disabled with `handle_interrupt`); other signals keep their action.
+    #[cfg(target_os = "linux")]
+    pub fn on_signal<F>(mut self, kind: SignalKind, handler: F) -> Self
+    where
+        F: Fn(&Self) + 'static,
+    {
+        self.signal_handlers.push((kind, Box::new(handler)));
+        self
+    }
+
+    /// Sets whether `SIGINT` (Ctrl-C) quits the loop gracefully instead of killing the process,
+    /// which is the default.
+    ///
+    /// Pass `false` to leave `SIGINT` to the host program. A handler registered for
+    /// `SignalKind::INTERRUPT` with `on_signal` replaces quitting either way.
+    #[cfg(target_os = "linux")]
+    pub fn handle_interrupt(mut self, enabled: bool) -> Self {
+        self.handle_interrupt = enabled;
+        self
+    }
+
     /// Starts the event loop with the provided entry point.
     /// The entry point is an async function that will be executed in the context of the loop.
-    pub fn run<'a, F, C>(self, entry: F)
+    ///
+    /// Returns the value passed to `quit_with`, or `None` if the loop was stopped with `quit`
+    /// or exited because all tasks completed.
+    ///
+    /// If the runtime cannot be created or fails to wait for events, the error is reported to
+    /// the failure handler and the loop exits, returning `None`.
+    pub fn run<'a, F, C, O>(mut self, entry: F) -> Option<T>
     where
         C: Component<'a> + 'a,
-        F: AsyncFn(Weak<C>) + 'a,
+        F: AsyncFn(Weak<C>) -> O + 'a,
+        O: ComponentOutput,
     {
-        let rt = Rc::new(Runtime::new());
+        let rt = match Runtime::with_parts(self.scheduler.clone(), Default::default()) {
+            Ok(rt) => Rc::new(rt),
+            Err(e) => {
+                let failure = Failure::from_error(None, Box::new(e));
+                match &self.failure_handler {
+                    Some(handler) => handler(failure),
+                    None => eprintln!("{}", failure),
+                }
+                return None;
+            }
+        };
+        match self.failure_handler.take() {
+            Some(handler) if self.catch_panics => rt.catch_panics(handler),
+            Some(handler) => rt.on_failure(handler),
+            None => (),
+        }
         let rt_weak = Rc::downgrade(&rt);
         let c = Rc::new(C::new(rt_weak.clone()));
         let c_weak = Rc::downgrade(&c);
-        rt.spawn(async move { entry(c_weak).await });
+        c.render(c.observe(async move { entry(c_weak).await }));
+        #[cfg(target_os = "linux")]
+        let signals = self.listen_signals(&rt);
+        let handlers = unsafe { &*self.handlers.get() };
+        // 没有轮询处理器时，空闲状态下可以一直休眠到下一个唤醒或定时器截止
+        let timeout = (!handlers.is_empty()).then_some(self.poll_interval);
+        let mut deadline = None;
         loop {
             rt.poll_all();
-            if unsafe { *self.quit_flag.get() } {
+            #[cfg(target_os = "linux")]
+            self.handle_signals(&signals);
+            if self.exit_when_done && rt.active_count() == 0 {
                 break;
             }
 
-            let handlers: &mut Vec<Box<dyn Fn(&Self)>> = unsafe { transmute(self.handlers.get()) };
-            for h in handlers.iter() {
-                h(&self);
+            if !self.should_quit() {
+                for h in handlers.iter() {
+                    h(&self);
+                }
+            }
+            if self.should_quit() {
+                // 通知组件开始关闭，并在截止时间之前等待它们完成清理
+                let deadline = *deadline.get_or_insert_with(|| {
+                    rt.shutdown();
+                    Instant::now() + self.shutdown_timeout
+                });
+                if !rt.has_shutdown_tasks() || Instant::now() >= deadline {
+                    break;
+                }
+            }
+
+            let remaining = deadline.map(|d: Instant| d.saturating_duration_since(Instant::now()));
+            let timeout = match (timeout, remaining) {
+                (Some(t), Some(r)) => Some(t.min(r)),
+                (t, r) => t.or(r),
+            };
+            if let Err(e) = rt.park(timeout) {
+                rt.report(Failure::from_error(None, Box::new(e)));
+                break;
+            }
+        }
+
+        self.quit_value.into_inner()
+    }
+
+    /// Returns the signals the loop listens for: every signal with a handler, and `SIGINT` if
+    /// the loop quits on it.
+    #[cfg(target_os = "linux")]
+    fn signal_kinds(&self) -> Vec<SignalKind> {
+        let mut kinds = Vec::new();
+        if self.handle_interrupt {
+            kinds.push(SignalKind::INTERRUPT);
+        }
+        for (kind, _) in &self.signal_handlers {
+            if !kinds.contains(kind) {
+                kinds.push(*kind);
+            }
+        }
+
+        kinds
+    }
+
+    /// Spawns a daemon task for every signal the loop listens for, collecting the received
+    /// signals.
+    #[cfg(target_os = "linux")]
+    fn listen_signals(&self, rt: &Runtime<'_>) -> Rc<RefCell<Vec<SignalKind>>> {
+        let received = Rc::<RefCell<Vec<_>>>::default();
+        for kind in self.signal_kinds() {
+            match SignalListener::new(kind) {
+                Ok(listener) => {
+                    let received = received.clone();
+                    rt.spawn_daemon(async move {
+                        loop {
+                            let kind = listener.listen().await;
+                            received.borrow_mut().push(kind);
+                        }
+                    });
+                }
+                Err(e) => {
+                    let e = Error::new(e.kind(), format!("failed to listen for {:?}: {}", kind, e));
+                    rt.report(Failure::from_error(None, Box::new(e)));
+                }
             }
         }
+
+        received
+    }
+
+    #[cfg(target_os = "linux")]
+    fn handle_signals(&self, signals: &RefCell<Vec<SignalKind>>) {
+        for kind in take(&mut *signals.borrow_mut()) {
+            let mut handled = false;
+            for (_, h) in self.signal_handlers.iter().filter(|(k, _)| *k == kind) {
+                h(self);
+                handled = true;
+            }
+            // 默认情况下，收到 SIGINT 会退出事件循环
+            if !handled && kind == SignalKind::INTERRUPT && self.handle_interrupt {
+                self.quit();
+            }
+        }
+    }
+
+    fn should_quit(&self) -> bool {
+        let quit = unsafe { *self.quit_flag.get() };
+        quit || self.scheduler.quit_requested()
     }
 }
 
 /// Convenience function that creates a new event loop and runs it with the provided entry point.
 /// This is equivalent to `Loop::new().run(entry)`.
-pub fn run<'a, F, C>(entry: F)
+pub fn run<'a, F, C, O>(entry: F)
 where
     C: Component<'a> + 'a,
-    F: AsyncFn(Weak<C>) + 'a,
+    F: AsyncFn(Weak<C>) -> O + 'a,
+    O: ComponentOutput,
 {
-    Loop::new().run(entry)
+    Loop::new().run(entry);
+}
+
+#[cfg(test)]
+mod tests {
+    use {crate::prelude::*, std::process::ExitCode};
+
+    #[component]
+    async fn idle() {}
+
+    #[test]
+    fn test_quit_with_value() {
+        let code = Loop::default()
+            .register_poll_handler(|l| l.quit_with(ExitCode::from(3)))
+            .run(idle);
+        assert_eq!(code, Some(ExitCode::from(3)));
+    }
+
+    #[component]
+    async fn with_daemon() {
+        this.spawn_daemon(sleep(Duration::from_secs(3600)));
+        sleep(Duration::from_millis(10)).await;
+    }
+
+    #[test]
+    fn test_exit_when_done_ignores_daemons() {
+        let code = Loop::<ExitCode>::default()
+            .exit_when_done()
+            .run(with_daemon);
+        assert_eq!(code, None);
+    }
+
+    thread_local! {
+        static FLUSHED: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+    }
+
+    #[component]
+    async fn flusher() {
+        this.on_shutdown().await;
+        sleep(Duration::from_millis(10)).await;
+        FLUSHED.set(true);
+        // 超过关闭时限仍未完成的任务会被直接丢弃
+        sleep(Duration::from_secs(3600)).await;
+    }
+
+    #[test]
+    fn test_shutdown_waits_for_cleanup() {
+        let start = std::time::Instant::now();
+        Loop::new()
+            .shutdown_timeout(Duration::from_millis(50))
+            .register_poll_handler(|l| l.quit())
+            .run(flusher);
+        assert!(FLUSHED.get());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[component]
+    async fn raiser() {
+        sleep(Duration::from_millis(10)).await;
+        unsafe { libc::raise(libc::SIGUSR2) };
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_on_signal() {
+        let code = Loop::default()
+            .on_signal(SignalKind::USER_DEFINED2, |l| {
+                l.quit_with(ExitCode::from(7))
+            })
+            .run(raiser);
+        assert_eq!(code, Some(ExitCode::from(7)));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_interrupt_is_handled_by_default() {
+        assert_eq!(Loop::new().signal_kinds(), [SignalKind::INTERRUPT]);
+        assert!(
+            Loop::new()
+                .handle_interrupt(false)
+                .signal_kinds()
+                .is_empty()
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_signal_errors_are_reported() {
+        let reported = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let reported2 = reported.clone();
+        Loop::new()
+            .exit_when_done()
+            .on_failure(move |f| reported2.borrow_mut().push(f.to_string()))
+            .on_signal(SignalKind::from_raw(libc::SIGKILL), |_| ())
+            .run(idle);
+        assert!(matches!(
+            reported.borrow().as_slice(),
+            [m] if m.contains("failed to listen for")
+        ));
+    }
 }
@@ -0,0 +1,45 @@
+use std::any::Any;
+
+/// The cached value of a `#[memo] let` in a component body.
+///
+/// The value is kept with the inputs it was computed from; it is computed again only when it is
+/// requested with different inputs, so re-rendering the component does not recompute it. The
+/// inputs and the value are stored as `Any`, so they must not borrow from the component.
+#[derive(Default)]
+pub struct Memoized {
+    cached: Option<Box<dyn Any>>,
+}
+
+impl Memoized {
+    /// Returns the value computed from `inputs`, calling `f` if the inputs differ from the last
+    /// ones.
+    pub fn get<I, T>(&mut self, inputs: I, f: impl FnOnce() -> T) -> T
+    where
+        I: PartialEq + 'static,
+        T: Clone + 'static,
+    {
+        if let Some((cached_inputs, value)) = self
+            .cached
+            .as_ref()
+            .and_then(|c| c.downcast_ref::<(I, T)>())
+            && *cached_inputs == inputs
+        {
+            return value.clone();
+        }
+
+        self.refresh(inputs, f)
+    }
+
+    /// Computes the value again even if the inputs are equal to the last ones (for example an
+    /// input was changed through interior mutability).
+    pub fn refresh<I, T>(&mut self, inputs: I, f: impl FnOnce() -> T) -> T
+    where
+        I: 'static,
+        T: Clone + 'static,
+    {
+        let value = f();
+        self.cached = Some(Box::new((inputs, value.clone())));
+
+        value
+    }
+}
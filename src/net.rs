@@ -0,0 +1,485 @@
+//! Asynchronous TCP and Unix domain sockets driven by the reactor of the compo runtime.
+//!
+//! The sockets must be created while a task of a compo runtime is being polled, e.g. in a
+//! component body, and their methods are futures that can be awaited there:
+//!
+//! ```no_run
+//! use compo::{net::TcpStream, prelude::*};
+//!
+//! #[component]
+//! async fn status() -> std::io::Result<()> {
+//!     let stream = TcpStream::connect("127.0.0.1:7000").await?;
+//!     stream.write_all(b"status\n").await?;
+//!     let mut buf = [0; 512];
+//!     let n = stream.read(&mut buf).await?;
+//!     println!("{}", String::from_utf8_lossy(&buf[..n]));
+//!     Ok(())
+//! }
+//! ```
+
+use {
+    crate::{blocking::spawn_blocking, reactor::AsyncFd},
+    libc::{
+        AF_INET, AF_INET6, AF_UNIX, EINPROGRESS, SOCK_CLOEXEC, SOCK_NONBLOCK, SOCK_STREAM,
+        sockaddr, sockaddr_in, sockaddr_in6, sockaddr_un, socklen_t,
+    },
+    std::{
+        io::{Error, ErrorKind, Read, Result as IoResult, Write},
+        mem::{size_of, zeroed},
+        net::{
+            self, IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, SocketAddrV4, SocketAddrV6,
+            ToSocketAddrs,
+        },
+        os::{
+            fd::{AsRawFd, FromRawFd, OwnedFd},
+            unix::{ffi::OsStrExt, net as unix},
+        },
+        path::Path,
+    },
+};
+
+/// Creates a non-blocking stream socket and starts connecting it to `addr`.
+fn start_connect(family: i32, addr: *const sockaddr, len: socklen_t) -> IoResult<OwnedFd> {
+    let fd = unsafe { libc::socket(family, SOCK_STREAM | SOCK_NONBLOCK | SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(Error::last_os_error());
+    }
+
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    if unsafe { libc::connect(fd.as_raw_fd(), addr, len) } < 0 {
+        let e = Error::last_os_error();
+        // 非阻塞连接正在进行中，等待套接字可写后再检查结果
+        if e.raw_os_error() != Some(EINPROGRESS) {
+            return Err(e);
+        }
+    }
+
+    Ok(fd)
+}
+
+fn connect_inet(addr: &SocketAddr) -> IoResult<OwnedFd> {
+    match addr {
+        SocketAddr::V4(addr) => {
+            let mut raw: sockaddr_in = unsafe { zeroed() };
+            raw.sin_family = AF_INET as _;
+            raw.sin_port = addr.port().to_be();
+            raw.sin_addr.s_addr = u32::from_ne_bytes(addr.ip().octets());
+            let len = size_of::<sockaddr_in>() as _;
+            start_connect(AF_INET, (&raw as *const sockaddr_in).cast(), len)
+        }
+        SocketAddr::V6(addr) => {
+            let mut raw: sockaddr_in6 = unsafe { zeroed() };
+            raw.sin6_family = AF_INET6 as _;
+            raw.sin6_port = addr.port().to_be();
+            raw.sin6_flowinfo = addr.flowinfo();
+            raw.sin6_addr.s6_addr = addr.ip().octets();
+            raw.sin6_scope_id = addr.scope_id();
+            let len = size_of::<sockaddr_in6>() as _;
+            start_connect(AF_INET6, (&raw as *const sockaddr_in6).cast(), len)
+        }
+    }
+}
+
+fn connect_unix(path: &Path) -> IoResult<OwnedFd> {
+    let mut raw: sockaddr_un = unsafe { zeroed() };
+    raw.sun_family = AF_UNIX as _;
+    let bytes = path.as_os_str().as_bytes();
+    if bytes.len() >= raw.sun_path.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "socket path is too long",
+        ));
+    }
+    for (dst, src) in raw.sun_path.iter_mut().zip(bytes) {
+        *dst = *src as _;
+    }
+    let len = size_of::<sockaddr_un>() as _;
+    start_connect(AF_UNIX, (&raw as *const sockaddr_un).cast(), len)
+}
+
+mod sealed {
+    use std::net::SocketAddr;
+
+    /// The addresses to connect to, or a `host:port` string to resolve first.
+    pub enum ConnectAddrs {
+        Resolved(Vec<SocketAddr>),
+        Host(String),
+    }
+
+    pub trait Sealed {
+        fn connect_addrs(&self) -> ConnectAddrs;
+    }
+}
+
+use sealed::{ConnectAddrs, Sealed};
+
+/// Addresses accepted by `TcpStream::connect`.
+///
+/// It is implemented for the same types as `std::net::ToSocketAddrs`, borrowed host names
+/// included. Socket addresses and IP literals are used directly, while host names are copied
+/// so that they can be resolved on the blocking thread pool.
+pub trait ToConnectAddrs: Sealed {}
+
+impl<T: Sealed + ?Sized> ToConnectAddrs for T {}
+
+macro_rules! impl_resolved {
+    ($($t:ty),*) => {
+        $(
+            impl Sealed for $t {
+                fn connect_addrs(&self) -> ConnectAddrs {
+                    ConnectAddrs::Resolved(vec![SocketAddr::from(*self)])
+                }
+            }
+        )*
+    };
+}
+
+impl_resolved!(
+    SocketAddr,
+    SocketAddrV4,
+    SocketAddrV6,
+    (IpAddr, u16),
+    (Ipv4Addr, u16),
+    (Ipv6Addr, u16)
+);
+
+impl Sealed for (&str, u16) {
+    fn connect_addrs(&self) -> ConnectAddrs {
+        let (host, port) = *self;
+        match host.parse::<IpAddr>() {
+            Ok(ip) => ConnectAddrs::Resolved(vec![SocketAddr::new(ip, port)]),
+            Err(_) => ConnectAddrs::Host(format!("{}:{}", host, port)),
+        }
+    }
+}
+
+impl Sealed for (String, u16) {
+    fn connect_addrs(&self) -> ConnectAddrs {
+        (self.0.as_str(), self.1).connect_addrs()
+    }
+}
+
+impl Sealed for str {
+    fn connect_addrs(&self) -> ConnectAddrs {
+        match self.parse() {
+            Ok(addr) => ConnectAddrs::Resolved(vec![addr]),
+            Err(_) => ConnectAddrs::Host(self.to_owned()),
+        }
+    }
+}
+
+impl Sealed for String {
+    fn connect_addrs(&self) -> ConnectAddrs {
+        self.as_str().connect_addrs()
+    }
+}
+
+impl Sealed for [SocketAddr] {
+    fn connect_addrs(&self) -> ConnectAddrs {
+        ConnectAddrs::Resolved(self.to_vec())
+    }
+}
+
+impl<T: Sealed + ?Sized> Sealed for &T {
+    fn connect_addrs(&self) -> ConnectAddrs {
+        (**self).connect_addrs()
+    }
+}
+
+/// A TCP socket server, listening for connections.
+pub struct TcpListener {
+    inner: AsyncFd<net::TcpListener>,
+}
+
+impl TcpListener {
+    /// Creates a listener bound to `addr`.
+    pub fn bind<A>(addr: A) -> IoResult<Self>
+    where
+        A: ToSocketAddrs,
+    {
+        Self::from_std(net::TcpListener::bind(addr)?)
+    }
+
+    /// Registers a standard listener with the reactor of the current runtime.
+    pub fn from_std(listener: net::TcpListener) -> IoResult<Self> {
+        Ok(Self {
+            inner: AsyncFd::new(listener)?,
+        })
+    }
+
+    /// Waits for a new incoming connection.
+    pub async fn accept(&self) -> IoResult<(TcpStream, SocketAddr)> {
+        let (stream, addr) = self.inner.read_with(|l| l.accept()).await?;
+        Ok((TcpStream::from_std(stream)?, addr))
+    }
+
+    /// Returns the local address this listener is bound to.
+    pub fn local_addr(&self) -> IoResult<SocketAddr> {
+        self.inner.get_ref().local_addr()
+    }
+}
+
+/// A TCP stream between a local and a remote socket.
+pub struct TcpStream {
+    inner: AsyncFd<net::TcpStream>,
+}
+
+impl TcpStream {
+    /// Opens a TCP connection to `addr`, trying each resolved address in turn.
+    ///
+    /// Host names are resolved with `spawn_blocking`, so a slow DNS lookup does not stall the
+    /// event loop.
+    pub async fn connect<A>(addr: A) -> IoResult<Self>
+    where
+        A: ToConnectAddrs,
+    {
+        let addrs = match addr.connect_addrs() {
+            ConnectAddrs::Resolved(addrs) => addrs,
+            // 主机名解析可能阻塞，交给阻塞线程池
+            ConnectAddrs::Host(host) => {
+                spawn_blocking(move || host.to_socket_addrs().map(Vec::from_iter))
+                    .await
+                    .map_err(Error::other)??
+            }
+        };
+        let mut last_error = None;
+        for addr in addrs {
+            match Self::connect_addr(&addr).await {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            Error::new(ErrorKind::InvalidInput, "could not resolve to any address")
+        }))
+    }
+
+    async fn connect_addr(addr: &SocketAddr) -> IoResult<Self> {
+        let stream = Self::from_std(connect_inet(addr)?.into())?;
+        // 连接完成时套接字变为可写，连接的结果保存在 SO_ERROR 中
+        stream.inner.writable().await;
+        match stream.inner.get_ref().take_error()? {
+            Some(e) => Err(e),
+            None => Ok(stream),
+        }
+    }
+
+    /// Registers a standard stream with the reactor of the current runtime.
+    pub fn from_std(stream: net::TcpStream) -> IoResult<Self> {
+        Ok(Self {
+            inner: AsyncFd::new(stream)?,
+        })
+    }
+
+    /// Reads some bytes into `buf`, returning how many were read; `0` means end of stream.
+    pub async fn read(&self, buf: &mut [u8]) -> IoResult<usize> {
+        self.inner.read_with(|mut s| s.read(buf)).await
+    }
+
+    /// Writes some bytes from `buf`, returning how many were written.
+    pub async fn write(&self, buf: &[u8]) -> IoResult<usize> {
+        self.inner.write_with(|mut s| s.write(buf)).await
+    }
+
+    /// Writes the whole of `buf`.
+    pub async fn write_all(&self, mut buf: &[u8]) -> IoResult<()> {
+        while !buf.is_empty() {
+            match self.write(buf).await? {
+                0 => return Err(ErrorKind::WriteZero.into()),
+                n => buf = &buf[n..],
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Shuts down the read half, the write half or both halves of the connection.
+    pub fn shutdown(&self, how: Shutdown) -> IoResult<()> {
+        self.inner.get_ref().shutdown(how)
+    }
+
+    /// Returns the local address of this stream.
+    pub fn local_addr(&self) -> IoResult<SocketAddr> {
+        self.inner.get_ref().local_addr()
+    }
+
+    /// Returns the address of the remote peer.
+    pub fn peer_addr(&self) -> IoResult<SocketAddr> {
+        self.inner.get_ref().peer_addr()
+    }
+
+    /// Sets the `TCP_NODELAY` option of the socket.
+    pub fn set_nodelay(&self, nodelay: bool) -> IoResult<()> {
+        self.inner.get_ref().set_nodelay(nodelay)
+    }
+}
+
+/// A Unix domain socket server, listening for connections.
+pub struct UnixListener {
+    inner: AsyncFd<unix::UnixListener>,
+}
+
+impl UnixListener {
+    /// Creates a listener bound to the socket file at `path`.
+    pub fn bind<P>(path: P) -> IoResult<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::from_std(unix::UnixListener::bind(path)?)
+    }
+
+    /// Registers a standard listener with the reactor of the current runtime.
+    pub fn from_std(listener: unix::UnixListener) -> IoResult<Self> {
+        Ok(Self {
+            inner: AsyncFd::new(listener)?,
+        })
+    }
+
+    /// Waits for a new incoming connection.
+    pub async fn accept(&self) -> IoResult<(UnixStream, unix::SocketAddr)> {
+        let (stream, addr) = self.inner.read_with(|l| l.accept()).await?;
+        Ok((UnixStream::from_std(stream)?, addr))
+    }
+
+    /// Returns the local address this listener is bound to.
+    pub fn local_addr(&self) -> IoResult<unix::SocketAddr> {
+        self.inner.get_ref().local_addr()
+    }
+}
+
+/// A Unix domain stream socket.
+pub struct UnixStream {
+    inner: AsyncFd<unix::UnixStream>,
+}
+
+impl UnixStream {
+    /// Connects to the socket file at `path`.
+    pub async fn connect<P>(path: P) -> IoResult<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let stream = Self::from_std(connect_unix(path.as_ref())?.into())?;
+        stream.inner.writable().await;
+        match stream.inner.get_ref().take_error()? {
+            Some(e) => Err(e),
+            None => Ok(stream),
+        }
+    }
+
+    /// Creates a pair of connected sockets.
+    pub fn pair() -> IoResult<(Self, Self)> {
+        let (a, b) = unix::UnixStream::pair()?;
+        Ok((Self::from_std(a)?, Self::from_std(b)?))
+    }
+
+    /// Registers a standard stream with the reactor of the current runtime.
+    pub fn from_std(stream: unix::UnixStream) -> IoResult<Self> {
+        Ok(Self {
+            inner: AsyncFd::new(stream)?,
+        })
+    }
+
+    /// Reads some bytes into `buf`, returning how many were read; `0` means end of stream.
+    pub async fn read(&self, buf: &mut [u8]) -> IoResult<usize> {
+        self.inner.read_with(|mut s| s.read(buf)).await
+    }
+
+    /// Writes some bytes from `buf`, returning how many were written.
+    pub async fn write(&self, buf: &[u8]) -> IoResult<usize> {
+        self.inner.write_with(|mut s| s.write(buf)).await
+    }
+
+    /// Writes the whole of `buf`.
+    pub async fn write_all(&self, mut buf: &[u8]) -> IoResult<()> {
+        while !buf.is_empty() {
+            match self.write(buf).await? {
+                0 => return Err(ErrorKind::WriteZero.into()),
+                n => buf = &buf[n..],
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Shuts down the read half, the write half or both halves of the connection.
+    pub fn shutdown(&self, how: Shutdown) -> IoResult<()> {
+        self.inner.get_ref().shutdown(how)
+    }
+
+    /// Returns the local address of this socket.
+    pub fn local_addr(&self) -> IoResult<unix::SocketAddr> {
+        self.inner.get_ref().local_addr()
+    }
+
+    /// Returns the address of the remote peer.
+    pub fn peer_addr(&self) -> IoResult<unix::SocketAddr> {
+        self.inner.get_ref().peer_addr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        crate::{
+            net::{TcpListener, TcpStream, UnixListener, UnixStream},
+            runtime::Runtime,
+        },
+        std::{cell::RefCell, env::temp_dir, process, rc::Rc},
+    };
+
+    #[test]
+    fn test_tcp_echo() {
+        let rt = Rc::new(Runtime::new().unwrap());
+        let reply = Rc::new(RefCell::new(Vec::new()));
+        let reply2 = reply.clone();
+        let rt2 = rt.clone();
+        rt.spawn(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            rt2.spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 16];
+                let n = stream.read(&mut buf).await.unwrap();
+                stream.write_all(&buf[..n]).await.unwrap();
+            });
+            let host = String::from("localhost");
+            let stream = TcpStream::connect((host.as_str(), addr.port()))
+                .await
+                .unwrap();
+            stream.write_all(b"echo").await.unwrap();
+            let mut buf = [0; 16];
+            let n = stream.read(&mut buf).await.unwrap();
+            reply2.borrow_mut().extend_from_slice(&buf[..n]);
+        });
+        rt.run_until_done();
+
+        assert_eq!(*reply.borrow(), b"echo");
+    }
+
+    #[test]
+    fn test_unix_connect() {
+        let path = temp_dir().join(format!("compo-test-{}.sock", process::id()));
+        let rt = Rc::new(Runtime::new().unwrap());
+        let reply = Rc::new(RefCell::new(Vec::new()));
+        let reply2 = reply.clone();
+        let rt2 = rt.clone();
+        let path2 = path.clone();
+        rt.spawn(async move {
+            let listener = UnixListener::bind(&path2).unwrap();
+            rt2.spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                stream.write_all(b"hello").await.unwrap();
+            });
+            let stream = UnixStream::connect(&path2).await.unwrap();
+            let mut buf = [0; 16];
+            let n = stream.read(&mut buf).await.unwrap();
+            reply2.borrow_mut().extend_from_slice(&buf[..n]);
+            assert!(UnixStream::pair().is_ok());
+        });
+        rt.run_until_done();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(*reply.borrow(), b"hello");
+    }
+}
@@ -1,16 +1,30 @@
 pub use {
     crate::{
-        component::Component,
+        blocking::{Blocking, spawn_blocking},
+        component::{Component, ComponentOutput},
         event::{EventEmitter, EventListener},
+        failure::{Failure, FailureCause},
+        keyed::Keyed,
         r#loop::{Loop, run},
-        runtime::{Cancellable, Runtime},
+        memoized::Memoized,
+        proxy::LoopProxy,
+        reactive::{Effect, Memo, Signal},
+        runtime::{Cancellable, Cancelled, JoinError, JoinHandle, Runtime, Shutdown},
+        testing::TestRuntime,
         time::{Duration, sleep},
+        touch,
     },
     compo_macros::component,
     futures_util::join,
     std::{
-        cell::{RefCell, UnsafeCell},
+        cell::{Cell, RefCell, UnsafeCell},
         mem::transmute,
         rc::{Rc, Weak},
     },
 };
+
+#[cfg(target_os = "linux")]
+pub use crate::{
+    reactor::AsyncFd,
+    signal::{SignalKind, SignalListener},
+};
@@ -0,0 +1,102 @@
+use {
+    crate::runtime::Scheduler,
+    std::{any::Any, sync::Arc},
+};
+
+/// Work posted into the event loop from another thread.
+pub(crate) enum Posted {
+    /// A closure to call on the loop thread.
+    Call(Box<dyn FnOnce() + Send>),
+    /// A typed message for the handler registered with `Runtime::on_message`.
+    Message(Box<dyn Any + Send>),
+}
+
+/// A handle for interacting with an event loop from other threads.
+///
+/// Obtained from `Loop::proxy` or `Runtime::proxy`. Everything posted through the proxy is
+/// delivered on the loop thread at the start of the next `poll_all`, and posting wakes the loop
+/// if it is parked:
+///
+/// ```
+/// use compo::prelude::*;
+///
+/// let r#loop = Loop::new().exit_when_done();
+/// let proxy = r#loop.proxy();
+/// r#loop.run(async move |this: Weak<App>| {
+///     let proxy = proxy.clone();
+///     std::thread::spawn(move || proxy.post(|| println!("hello from the loop thread")));
+///     sleep(Duration::from_millis(10)).await;
+/// });
+/// # #[component]
+/// # async fn app() {}
+/// ```
+#[derive(Clone)]
+pub struct LoopProxy {
+    scheduler: Arc<Scheduler>,
+}
+
+impl LoopProxy {
+    pub(crate) fn new(scheduler: Arc<Scheduler>) -> Self {
+        Self { scheduler }
+    }
+
+    /// Calls `f` on the loop thread.
+    pub fn post<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.scheduler.post(Posted::Call(Box::new(f)));
+    }
+
+    /// Sends `message` to the handler registered for `M` with `Runtime::on_message`.
+    ///
+    /// The message is dropped if no handler for `M` is registered when it is delivered.
+    pub fn send<M>(&self, message: M)
+    where
+        M: Send + 'static,
+    {
+        self.scheduler.post(Posted::Message(Box::new(message)));
+    }
+
+    /// Wakes the loop if it is parked, so its poll handlers run again.
+    pub fn wake(&self) {
+        self.scheduler.unpark();
+    }
+
+    /// Signals the event loop to stop running, like `Loop::quit`.
+    pub fn quit(&self) {
+        self.scheduler.request_quit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        crate::runtime::Runtime,
+        std::{cell::Cell, rc::Rc, thread},
+    };
+
+    #[test]
+    fn test_proxy_delivers_messages_on_loop_thread() {
+        let rt = Runtime::new().unwrap();
+        let received = Rc::new(Cell::new(0));
+        let received2 = received.clone();
+        rt.on_message(move |m: u32| received2.set(received2.get() + m));
+        let proxy = rt.proxy();
+        let loop_thread = thread::current().id();
+        thread::spawn(move || {
+            proxy.send(5u32);
+            // 没有注册处理器的消息会被丢弃
+            proxy.send("ignored");
+            proxy.post(move || assert_eq!(thread::current().id(), loop_thread));
+        })
+        .join()
+        .unwrap();
+
+        // 投递的消息会唤醒挂起的线程
+        assert!(rt.has_ready());
+        rt.park(None).unwrap();
+        rt.poll_all();
+        assert_eq!(received.get(), 5);
+    }
+}
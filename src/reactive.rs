@@ -0,0 +1,443 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt::{Debug, Formatter, Result as FmtResult},
+    mem::take,
+    pin::Pin,
+    rc::{Rc, Weak},
+    task::{Context, Poll},
+};
+
+thread_local! {
+    /// The observer recording the signals read by the code currently running.
+    static OBSERVER: RefCell<Option<Rc<Observer>>> = const { RefCell::new(None) };
+}
+
+/// Something that depends on signals, notified when one of them changes.
+pub(crate) struct Observer {
+    notify: Box<dyn Fn()>,
+}
+
+/// Runs `f` with `observer` recording the signals it reads.
+fn with_observer<R>(observer: Option<&Rc<Observer>>, f: impl FnOnce() -> R) -> R {
+    let previous = OBSERVER.replace(observer.cloned());
+    let output = f();
+    OBSERVER.set(previous);
+
+    output
+}
+
+/// The observers depending on a signal or a memo.
+#[derive(Default)]
+struct Subscribers(RefCell<Vec<Weak<Observer>>>);
+
+impl Subscribers {
+    /// Subscribes the current observer, if any.
+    fn track(&self) {
+        let Some(observer) = OBSERVER.with_borrow(|o| o.as_ref().map(Rc::downgrade)) else {
+            return;
+        };
+        let mut observers = self.0.borrow_mut();
+        observers.retain(|o| o.strong_count() > 0);
+        if !observers.iter().any(|o| o.ptr_eq(&observer)) {
+            observers.push(observer);
+        }
+    }
+
+    /// Notifies and unsubscribes every observer; they subscribe again when they read the
+    /// value next time.
+    fn notify(&self) {
+        for observer in take(&mut *self.0.borrow_mut())
+            .iter()
+            .filter_map(Weak::upgrade)
+        {
+            (observer.notify)();
+        }
+    }
+}
+
+/// A value that notifies its readers when it changes.
+///
+/// Reading a signal with `get` or `with` inside a component body subscribes the component to it:
+/// when the signal is set, the component re-renders at the end of the tick, no matter where
+/// the write happens. Clones of a signal share the same value.
+///
+/// ```
+/// use compo::prelude::*;
+///
+/// #[component]
+/// async fn counter(count: Signal<u32>) {
+///     println!("count: {}", count.get());
+/// }
+///
+/// let rt = TestRuntime::new();
+/// let count = Signal::new(0);
+/// let c = rt.mount(counter);
+/// c.set_count(&count);
+/// rt.run_until_stalled(); // Prints "count: 0"
+/// count.set(1);
+/// rt.run_until_stalled(); // Prints "count: 1"
+/// ```
+pub struct Signal<T> {
+    inner: Rc<SignalInner<T>>,
+}
+
+struct SignalInner<T> {
+    value: RefCell<T>,
+    subscribers: Subscribers,
+}
+
+impl<T> Signal<T> {
+    /// Creates a signal holding `value`.
+    pub fn new(value: T) -> Self {
+        Self {
+            inner: Rc::new(SignalInner {
+                value: RefCell::new(value),
+                subscribers: Default::default(),
+            }),
+        }
+    }
+
+    /// Returns a clone of the value, subscribing the current observer.
+    pub fn get(&self) -> T
+    where
+        T: Clone,
+    {
+        self.with(T::clone)
+    }
+
+    /// Calls `f` with a reference to the value, subscribing the current observer.
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        self.inner.subscribers.track();
+        f(&self.inner.value.borrow())
+    }
+
+    /// Returns a clone of the value without subscribing the current observer.
+    pub fn get_untracked(&self) -> T
+    where
+        T: Clone,
+    {
+        self.inner.value.borrow().clone()
+    }
+
+    /// Replaces the value and notifies the subscribers.
+    pub fn set(&self, value: T) {
+        self.inner.value.replace(value);
+        self.inner.subscribers.notify();
+    }
+
+    /// Modifies the value in place and notifies the subscribers.
+    pub fn update(&self, f: impl FnOnce(&mut T)) {
+        f(&mut self.inner.value.borrow_mut());
+        self.inner.subscribers.notify();
+    }
+}
+
+impl<T> Clone for Signal<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Default for Signal<T>
+where
+    T: Default,
+{
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T> PartialEq for Signal<T> {
+    /// Two signals are equal if they are clones of each other.
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl<T> Debug for Signal<T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_tuple("Signal")
+            .field(&self.inner.value.borrow())
+            .finish()
+    }
+}
+
+/// A value derived from signals and other memos.
+///
+/// The value is computed when it is first read, and computed again on the next read after one
+/// of its inputs changed. Its readers are subscribed to it like to a signal.
+///
+/// ```
+/// use compo::prelude::*;
+///
+/// let page = Signal::new(1);
+/// let label = {
+///     let page = page.clone();
+///     Memo::new(move || format!("page {}", page.get()))
+/// };
+/// assert_eq!(label.get(), "page 1");
+/// page.set(2);
+/// assert_eq!(label.get(), "page 2");
+/// ```
+pub struct Memo<T> {
+    inner: Rc<MemoInner<T>>,
+}
+
+struct MemoInner<T> {
+    f: Box<dyn Fn() -> T>,
+    // 输入变化后值会被清除，下次读取时重新计算
+    value: RefCell<Option<T>>,
+    observer: Rc<Observer>,
+    subscribers: Subscribers,
+}
+
+impl<T> Memo<T>
+where
+    T: 'static,
+{
+    /// Creates a memo computing its value with `f`.
+    pub fn new(f: impl Fn() -> T + 'static) -> Self {
+        Self {
+            inner: Rc::new_cyclic(|inner: &Weak<MemoInner<T>>| {
+                let inner = inner.clone();
+                MemoInner {
+                    f: Box::new(f),
+                    value: Default::default(),
+                    observer: Rc::new(Observer {
+                        notify: Box::new(move || {
+                            if let Some(inner) = inner.upgrade() {
+                                inner.value.take();
+                                inner.subscribers.notify();
+                            }
+                        }),
+                    }),
+                    subscribers: Default::default(),
+                }
+            }),
+        }
+    }
+}
+
+impl<T> Memo<T> {
+    /// Returns a clone of the value, subscribing the current observer.
+    pub fn get(&self) -> T
+    where
+        T: Clone,
+    {
+        self.with(T::clone)
+    }
+
+    /// Calls `f` with a reference to the value, subscribing the current observer.
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        let inner = &self.inner;
+        inner.subscribers.track();
+        if inner.value.borrow().is_none() {
+            let value = with_observer(Some(&inner.observer), || (inner.f)());
+            inner.value.replace(Some(value));
+        }
+        f(inner.value.borrow().as_ref().unwrap())
+    }
+}
+
+impl<T> Clone for Memo<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> PartialEq for Memo<T> {
+    /// Two memos are equal if they are clones of each other.
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+/// Identifies a reaction of a runtime.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum ReactionKey {
+    /// A component, identified by its address.
+    Component(usize),
+    Effect(usize),
+}
+
+/// Reruns something when the signals it read change.
+struct Reaction<'a> {
+    observer: Option<Rc<Observer>>,
+    // 返回 false 表示反应的所有者已经不存在，可以移除
+    run: Rc<dyn Fn() -> bool + 'a>,
+}
+
+/// The reactions of a runtime and the queue of those whose signals changed.
+#[derive(Default)]
+pub(crate) struct Reactions<'a> {
+    reactions: RefCell<HashMap<ReactionKey, Reaction<'a>>>,
+    changed: Rc<RefCell<Vec<ReactionKey>>>,
+    next_effect: Cell<usize>,
+}
+
+impl<'a> Reactions<'a> {
+    /// Returns a new observer for the reaction `key`, replacing the previous one so the
+    /// signals read before are forgotten.
+    fn new_observer(&self, key: ReactionKey) -> Rc<Observer> {
+        let changed = Rc::downgrade(&self.changed);
+        let observer = Rc::new(Observer {
+            notify: Box::new(move || {
+                if let Some(changed) = changed.upgrade() {
+                    changed.borrow_mut().push(key);
+                }
+            }),
+        });
+        if let Some(reaction) = self.reactions.borrow_mut().get_mut(&key) {
+            reaction.observer = Some(observer.clone());
+        }
+
+        observer
+    }
+
+    /// Registers `run` as the reaction `key` and returns a new observer for it.
+    pub(crate) fn observe(&self, key: ReactionKey, run: Rc<dyn Fn() -> bool + 'a>) -> Rc<Observer> {
+        self.reactions.borrow_mut().insert(
+            key,
+            Reaction {
+                observer: None,
+                run,
+            },
+        );
+        self.new_observer(key)
+    }
+
+    /// Returns `true` if a reaction has to run.
+    pub(crate) fn has_changed(&self) -> bool {
+        !self.changed.borrow().is_empty()
+    }
+
+    /// Runs the reactions whose signals changed, until no signal changes anymore.
+    pub(crate) fn run_changed(&self) {
+        loop {
+            let mut changed = self.changed.take();
+            if changed.is_empty() {
+                break;
+            }
+
+            let mut seen = Vec::with_capacity(changed.len());
+            changed.retain(|k| {
+                let first = !seen.contains(k);
+                seen.push(*k);
+                first
+            });
+            for key in changed {
+                let run = self.reactions.borrow().get(&key).map(|r| r.run.clone());
+                if let Some(run) = run
+                    && !run()
+                {
+                    self.reactions.borrow_mut().remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Creates an effect running `f` now and again whenever a signal it read changes.
+    pub(crate) fn effect(self: &Rc<Self>, f: impl Fn() + 'a) -> Effect<'a> {
+        let key = ReactionKey::Effect(self.next_effect.get());
+        self.next_effect.set(self.next_effect.get() + 1);
+        let reactions = Rc::downgrade(self);
+        let run = Rc::new(move || {
+            if let Some(reactions) = reactions.upgrade() {
+                let observer = reactions.new_observer(key);
+                with_observer(Some(&observer), &f);
+            }
+            true
+        });
+        self.observe(key, run.clone());
+        run();
+
+        Effect {
+            key,
+            reactions: Rc::downgrade(self),
+        }
+    }
+}
+
+/// A side effect that reruns whenever a signal it read changes, created with
+/// `Runtime::effect` or `Component::effect`.
+///
+/// Reruns happen at the end of the tick in which the signal changed. Dropping the effect stops
+/// it.
+pub struct Effect<'a> {
+    key: ReactionKey,
+    reactions: Weak<Reactions<'a>>,
+}
+
+impl<'a> Effect<'a> {
+    /// Creates an effect that never runs, as if its runtime had been dropped.
+    pub(crate) fn detached() -> Self {
+        Self {
+            key: ReactionKey::Effect(0),
+            reactions: Weak::new(),
+        }
+    }
+}
+
+impl<'a> Drop for Effect<'a> {
+    fn drop(&mut self) {
+        if let Some(reactions) = self.reactions.upgrade() {
+            reactions.reactions.borrow_mut().remove(&self.key);
+        }
+    }
+}
+
+/// A future recording the signals read while it is polled.
+pub(crate) struct Tracked<Fut> {
+    pub(crate) observer: Option<Rc<Observer>>,
+    pub(crate) future: Pin<Box<Fut>>,
+}
+
+impl<Fut> Future for Tracked<Fut>
+where
+    Fut: Future,
+{
+    type Output = Fut::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        with_observer(this.observer.as_ref(), || this.future.as_mut().poll(cx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        crate::{prelude::*, reactive::Signal},
+        std::{cell::RefCell, rc::Rc},
+    };
+
+    #[test]
+    fn test_effect_reruns_when_signal_changes() {
+        let rt = Runtime::new().unwrap();
+        let a = Signal::new(1);
+        let b = Signal::new(10);
+        let sum = {
+            let (a, b) = (a.clone(), b.clone());
+            Memo::new(move || a.get() + b.get())
+        };
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let seen2 = seen.clone();
+        let effect = rt.effect(move || seen2.borrow_mut().push(sum.get()));
+        a.set(2);
+        b.set(20);
+        rt.poll_all();
+        drop(effect);
+        a.set(3);
+        rt.poll_all();
+
+        assert_eq!(*seen.borrow(), [11, 22]);
+    }
+}
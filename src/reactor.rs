@@ -0,0 +1,417 @@
+use {
+    libc::{
+        EFD_CLOEXEC, EFD_NONBLOCK, EINTR, EPOLL_CLOEXEC, EPOLL_CTL_ADD, EPOLL_CTL_DEL, EPOLLERR,
+        EPOLLET, EPOLLHUP, EPOLLIN, EPOLLOUT, EPOLLRDHUP, F_GETFL, F_SETFL, O_NONBLOCK,
+        epoll_event,
+    },
+    std::{
+        cell::{Cell, RefCell},
+        collections::HashMap,
+        future::poll_fn,
+        io::{Error, ErrorKind, Result as IoResult},
+        os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        rc::{Rc, Weak},
+        sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+        },
+        task::{Context, Poll, Waker},
+        time::Duration,
+    },
+};
+
+thread_local! {
+    // 当前正在轮询任务的运行时的 reactor
+    static CURRENT: RefCell<Option<Rc<Reactor>>> = const { RefCell::new(None) };
+}
+
+/// The epoll token of the eventfd used to wake a parked loop.
+const WAKE_TOKEN: u64 = u64::MAX;
+
+fn cvt(result: i32) -> IoResult<i32> {
+    if result < 0 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+/// An eventfd that wakes a runtime blocked in `Reactor::wait` from any thread.
+pub(crate) struct Notifier {
+    fd: OwnedFd,
+    // 避免在一次等待前重复写入 eventfd
+    notified: AtomicBool,
+}
+
+impl Notifier {
+    pub(crate) fn new() -> IoResult<Self> {
+        let fd = cvt(unsafe { libc::eventfd(0, EFD_CLOEXEC | EFD_NONBLOCK) })?;
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            notified: false.into(),
+        })
+    }
+
+    pub(crate) fn notify(&self) {
+        if !self.notified.swap(true, Ordering::AcqRel) {
+            let one = 1u64.to_ne_bytes();
+            unsafe { libc::write(self.fd.as_raw_fd(), one.as_ptr().cast(), one.len()) };
+        }
+    }
+
+    fn drain(&self) {
+        let mut buf = [0u8; 8];
+        unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+        // 必须在读取之后才清除标志，否则可能丢失唤醒
+        self.notified.store(false, Ordering::Release);
+    }
+}
+
+/// The readiness of a registered file descriptor and the wakers of the tasks awaiting it.
+#[derive(Default)]
+struct Io {
+    readable: Cell<bool>,
+    writable: Cell<bool>,
+    read_waker: RefCell<Option<Waker>>,
+    write_waker: RefCell<Option<Waker>>,
+}
+
+/// An epoll instance owned by a runtime, used to wake tasks waiting on file descriptors.
+///
+/// File descriptors are registered edge-triggered: readiness is remembered until the owner
+/// of the descriptor observes `WouldBlock` and clears it.
+pub(crate) struct Reactor {
+    epoll: OwnedFd,
+    notifier: Arc<Notifier>,
+    sources: RefCell<HashMap<u64, Rc<Io>>>,
+    next_token: Cell<u64>,
+}
+
+impl Reactor {
+    pub(crate) fn new(notifier: Arc<Notifier>) -> IoResult<Self> {
+        let epoll = cvt(unsafe { libc::epoll_create1(EPOLL_CLOEXEC) })?;
+        let reactor = Self {
+            epoll: unsafe { OwnedFd::from_raw_fd(epoll) },
+            notifier,
+            sources: Default::default(),
+            next_token: Default::default(),
+        };
+        reactor.add(reactor.notifier.fd.as_raw_fd(), WAKE_TOKEN, EPOLLIN as _)?;
+        Ok(reactor)
+    }
+
+    /// Makes this reactor the one `AsyncFd` registers with until the guard is dropped.
+    pub(crate) fn enter(self: &Rc<Self>) -> ReactorGuard {
+        let previous = CURRENT.with(|c| c.replace(Some(self.clone())));
+        ReactorGuard { previous }
+    }
+
+    fn add(&self, fd: RawFd, token: u64, events: u32) -> IoResult<()> {
+        let mut event = epoll_event { events, u64: token };
+        cvt(unsafe { libc::epoll_ctl(self.epoll.as_raw_fd(), EPOLL_CTL_ADD, fd, &mut event) })?;
+        Ok(())
+    }
+
+    fn register(&self, fd: RawFd) -> IoResult<(u64, Rc<Io>)> {
+        let token = self.next_token.get();
+        self.next_token.set(token + 1);
+        let events = EPOLLIN | EPOLLOUT | EPOLLRDHUP | EPOLLET;
+        self.add(fd, token, events as _)?;
+        let io = Rc::new(Io::default());
+        self.sources.borrow_mut().insert(token, io.clone());
+        Ok((token, io))
+    }
+
+    fn deregister(&self, fd: RawFd, token: u64) {
+        self.sources.borrow_mut().remove(&token);
+        unsafe {
+            libc::epoll_ctl(
+                self.epoll.as_raw_fd(),
+                EPOLL_CTL_DEL,
+                fd,
+                std::ptr::null_mut(),
+            )
+        };
+    }
+
+    /// Blocks until a registered file descriptor becomes ready, the runtime is notified or the
+    /// optional `timeout` elapses, then wakes the tasks waiting on the ready descriptors.
+    ///
+    /// Waiting is retried when it is interrupted by a signal.
+    pub(crate) fn wait(&self, timeout: Option<Duration>) -> IoResult<()> {
+        let timeout = timeout.map_or(-1, |t| {
+            // 向上取整，避免在截止时间之前醒来后反复空转
+            t.as_nanos().div_ceil(1_000_000).min(i32::MAX as _) as i32
+        });
+        let mut events = [epoll_event { events: 0, u64: 0 }; 64];
+        let n = loop {
+            let n = unsafe {
+                libc::epoll_wait(
+                    self.epoll.as_raw_fd(),
+                    events.as_mut_ptr(),
+                    events.len() as _,
+                    timeout,
+                )
+            };
+            if n >= 0 {
+                break n;
+            }
+            let e = Error::last_os_error();
+            if e.raw_os_error() != Some(EINTR) {
+                return Err(e);
+            }
+        };
+
+        let mut wakers = Vec::new();
+        {
+            let sources = self.sources.borrow();
+            for event in &events[..n as usize] {
+                let (flags, token) = (event.events as i32, event.u64);
+                if token == WAKE_TOKEN {
+                    self.notifier.drain();
+                    continue;
+                }
+                let Some(io) = sources.get(&token) else {
+                    continue;
+                };
+                if flags & (EPOLLIN | EPOLLRDHUP | EPOLLHUP | EPOLLERR) != 0 {
+                    io.readable.set(true);
+                    wakers.extend(io.read_waker.take());
+                }
+                if flags & (EPOLLOUT | EPOLLHUP | EPOLLERR) != 0 {
+                    io.writable.set(true);
+                    wakers.extend(io.write_waker.take());
+                }
+            }
+        }
+        for waker in wakers {
+            waker.wake();
+        }
+
+        Ok(())
+    }
+}
+
+/// Restores the previously entered reactor when dropped.
+pub(crate) struct ReactorGuard {
+    previous: Option<Rc<Reactor>>,
+}
+
+impl Drop for ReactorGuard {
+    fn drop(&mut self) {
+        CURRENT.with(|c| *c.borrow_mut() = self.previous.take());
+    }
+}
+
+/// A file descriptor registered with the reactor of the current runtime.
+///
+/// Component bodies await `readable()` or `writable()` instead of busy-checking the descriptor
+/// on every loop iteration. Readiness is sticky: once reported, it stays set until the
+/// operation on the descriptor returns `WouldBlock` and the readiness is cleared, which
+/// `read_with` and `write_with` do automatically:
+///
+/// ```
+/// use {compo::prelude::*, std::{io::Read, os::unix::net::UnixStream}};
+///
+/// async fn receive(stream: UnixStream) -> std::io::Result<Vec<u8>> {
+///     let fd = AsyncFd::new(stream)?;
+///     let mut buf = vec![0; 1024];
+///     let n = fd.read_with(|mut s| s.read(&mut buf)).await?;
+///     buf.truncate(n);
+///     Ok(buf)
+/// }
+/// ```
+///
+/// Must be created while a task of a compo runtime is being polled, e.g. in a component body.
+/// The descriptor is switched to non-blocking mode.
+pub struct AsyncFd<T>
+where
+    T: AsRawFd,
+{
+    inner: Option<T>,
+    token: u64,
+    io: Rc<Io>,
+    reactor: Weak<Reactor>,
+}
+
+impl<T> AsyncFd<T>
+where
+    T: AsRawFd,
+{
+    /// Registers `inner` with the reactor of the current runtime.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no compo runtime is polling the current task, or if the descriptor cannot be
+    /// registered with epoll.
+    pub fn new(inner: T) -> IoResult<Self> {
+        let Some(reactor) = CURRENT.with(|c| c.borrow().clone()) else {
+            return Err(Error::other(
+                "AsyncFd must be created inside a compo runtime",
+            ));
+        };
+        let fd = inner.as_raw_fd();
+        let flags = cvt(unsafe { libc::fcntl(fd, F_GETFL) })?;
+        cvt(unsafe { libc::fcntl(fd, F_SETFL, flags | O_NONBLOCK) })?;
+        let (token, io) = reactor.register(fd)?;
+
+        Ok(Self {
+            inner: Some(inner),
+            token,
+            io,
+            reactor: Rc::downgrade(&reactor),
+        })
+    }
+
+    /// Returns a shared reference to the wrapped descriptor.
+    pub fn get_ref(&self) -> &T {
+        self.inner.as_ref().unwrap()
+    }
+
+    /// Returns a mutable reference to the wrapped descriptor.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.as_mut().unwrap()
+    }
+
+    /// Deregisters the descriptor from the reactor and returns it.
+    pub fn into_inner(mut self) -> T {
+        self.deregister();
+        self.inner.take().unwrap()
+    }
+
+    /// Polls for read readiness, registering the task to be woken if not ready.
+    pub fn poll_readable(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.io.readable.get() {
+            Poll::Ready(())
+        } else {
+            self.io.read_waker.replace(Some(cx.waker().clone()));
+            Poll::Pending
+        }
+    }
+
+    /// Polls for write readiness, registering the task to be woken if not ready.
+    pub fn poll_writable(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.io.writable.get() {
+            Poll::Ready(())
+        } else {
+            self.io.write_waker.replace(Some(cx.waker().clone()));
+            Poll::Pending
+        }
+    }
+
+    /// Waits until the descriptor is readable.
+    pub async fn readable(&self) {
+        poll_fn(|cx| self.poll_readable(cx)).await
+    }
+
+    /// Waits until the descriptor is writable.
+    pub async fn writable(&self) {
+        poll_fn(|cx| self.poll_writable(cx)).await
+    }
+
+    /// Clears the read readiness, after a read returned `WouldBlock`.
+    pub fn clear_readable(&self) {
+        self.io.readable.set(false);
+    }
+
+    /// Clears the write readiness, after a write returned `WouldBlock`.
+    pub fn clear_writable(&self) {
+        self.io.writable.set(false);
+    }
+
+    /// Calls the non-blocking read operation `op` until it does not return `WouldBlock`,
+    /// waiting for read readiness in between.
+    pub async fn read_with<R>(&self, mut op: impl FnMut(&T) -> IoResult<R>) -> IoResult<R> {
+        loop {
+            match op(self.get_ref()) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    self.clear_readable();
+                    self.readable().await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Calls the non-blocking write operation `op` until it does not return `WouldBlock`,
+    /// waiting for write readiness in between.
+    pub async fn write_with<R>(&self, mut op: impl FnMut(&T) -> IoResult<R>) -> IoResult<R> {
+        loop {
+            match op(self.get_ref()) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    self.clear_writable();
+                    self.writable().await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn deregister(&self) {
+        if let Some(reactor) = self.reactor.upgrade()
+            && let Some(inner) = &self.inner
+        {
+            reactor.deregister(inner.as_raw_fd(), self.token);
+        }
+    }
+}
+
+impl<T> AsRawFd for AsyncFd<T>
+where
+    T: AsRawFd,
+{
+    fn as_raw_fd(&self) -> RawFd {
+        self.get_ref().as_raw_fd()
+    }
+}
+
+impl<T> Drop for AsyncFd<T>
+where
+    T: AsRawFd,
+{
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        crate::{reactor::AsyncFd, runtime::Runtime},
+        std::{
+            cell::RefCell,
+            io::{Read, Write},
+            os::unix::net::UnixStream,
+            rc::Rc,
+            thread,
+            time::{Duration, Instant},
+        },
+    };
+
+    #[test]
+    fn test_async_fd_wakes_on_readable() {
+        let rt = Runtime::new().unwrap();
+        let (a, mut b) = UnixStream::pair().unwrap();
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let received2 = received.clone();
+        rt.spawn(async move {
+            let a = AsyncFd::new(a).unwrap();
+            let mut buf = [0; 16];
+            let n = a.read_with(|mut s| s.read(&mut buf)).await.unwrap();
+            received2.borrow_mut().extend_from_slice(&buf[..n]);
+        });
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            b.write_all(b"ping").unwrap();
+            b
+        });
+        let start = Instant::now();
+        let polls = rt.run_until_done();
+        drop(writer.join());
+
+        assert_eq!(*received.borrow(), b"ping");
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        // 等待期间阻塞在 epoll 上，而不是反复轮询
+        assert!(polls < 5);
+    }
+}
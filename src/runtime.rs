@@ -1,120 +1,1112 @@
 use {
+    crate::{
+        failure::Failure,
+        proxy::{LoopProxy, Posted},
+        reactive::{Effect, Reactions},
+        time::Timer,
+    },
     futures_util::{FutureExt, future::LocalBoxFuture},
     std::{
+        any::{Any, TypeId},
         cell::{Cell, RefCell},
-        rc::Rc,
-        task::{Context, Waker},
+        collections::{HashMap, HashSet},
+        error::Error,
+        fmt::{Display, Formatter, Result as FmtResult},
+        io::Result as IoResult,
+        mem::take,
+        panic::{AssertUnwindSafe, catch_unwind},
+        pin::Pin,
+        rc::{self, Rc},
+        sync::{
+            Arc, Mutex, Weak,
+            atomic::{AtomicBool, Ordering},
+        },
+        task::{Context, Poll, Wake, Waker},
+        time::Duration,
     },
 };
 
+#[cfg(not(target_os = "linux"))]
+use std::thread::{self, Thread};
+#[cfg(target_os = "linux")]
+use {
+    crate::reactor::{Notifier, Reactor},
+    std::sync::OnceLock,
+};
+
 /// A cancellable handle that can be used to cancel a spawned future
 ///
-/// This struct provides two main functionalities:
+/// This struct provides three main functionalities:
 /// 1. `cancel()` - Explicitly cancels the associated future
 /// 2. `is_cancelled()` - Checks if the future has been cancelled
+/// 3. `cancelled()` - Waits until the future has been cancelled
 ///
-/// The cancellation is cooperative - the future must explicitly check
-/// the cancellation status during execution
+/// A cancelled future is never polled again: the runtime drops it as soon as the
+/// currently running task yields, or at the start of the next `poll_all`.
 #[derive(Clone)]
 pub struct Cancellable {
-    _cancelled: Rc<Cell<bool>>,
+    state: Rc<CancelState>,
+}
+
+#[derive(Default)]
+struct CancelState {
+    cancelled: Cell<bool>,
+    panicked: Cell<bool>,
+    waiters: RefCell<Vec<Waker>>,
+    // 关联的任务 id，以及负责丢弃它的调度器
+    task: Option<(usize, Weak<Scheduler>)>,
 }
 
 impl Cancellable {
-    fn new() -> Self {
+    fn new(task: Option<(usize, Weak<Scheduler>)>) -> Self {
         Self {
-            _cancelled: Default::default(),
+            state: Rc::new(CancelState {
+                task,
+                ..Default::default()
+            }),
         }
     }
 
     pub fn cancel(&self) {
-        self._cancelled.set(true);
+        if self.state.cancelled.replace(true) {
+            return;
+        }
+
+        if let Some((id, scheduler)) = &self.state.task
+            && let Some(scheduler) = scheduler.upgrade()
+        {
+            scheduler.cancel(*id);
+        }
+        for waker in self.state.waiters.take() {
+            waker.wake();
+        }
     }
 
     pub fn is_cancelled(&self) -> bool {
-        self._cancelled.get()
+        self.state.cancelled.get()
+    }
+
+    /// Returns a future that resolves once this handle has been cancelled.
+    ///
+    /// Useful for tying helper futures to the lifetime of a task, e.g. by selecting
+    /// on it together with the work to be done.
+    pub fn cancelled(&self) -> Cancelled {
+        Cancelled {
+            cancellable: self.clone(),
+        }
     }
 }
 
 impl Default for Cancellable {
     fn default() -> Self {
-        Self::new()
+        Self::new(None)
     }
 }
 
-struct Task<'a, R> {
+/// A future that resolves when the associated `Cancellable` is cancelled.
+///
+/// This future is created by calling `cancelled()` on a `Cancellable`.
+pub struct Cancelled {
     cancellable: Cancellable,
-    future: LocalBoxFuture<'a, R>,
 }
 
-//noinspection SpellCheckingInspection
-pub struct Runtime<'a, R> {
-    context: RefCell<Context<'a>>,
-    pendings: RefCell<Vec<Task<'a, R>>>,
-    // 用于在 poll_all 过程中收集新的 futures
-    new_pendings: RefCell<Vec<Task<'a, R>>>,
-    // 标记是否正在执行 poll_all
-    polling: Cell<bool>,
+impl Future for Cancelled {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let state = &self.cancellable.state;
+        if state.cancelled.get() {
+            return Poll::Ready(());
+        }
+
+        let mut waiters = state.waiters.borrow_mut();
+        if !waiters.iter().any(|w| w.will_wake(cx.waker())) {
+            waiters.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// A future that resolves when the runtime begins shutting down, see `Runtime::on_shutdown`.
+pub struct Shutdown<'a> {
+    rt: rc::Weak<Runtime<'a>>,
+}
+
+impl<'a> Default for Shutdown<'a> {
+    /// Creates a future that resolves immediately, as if its runtime had been dropped.
+    fn default() -> Self {
+        Self {
+            rt: rc::Weak::new(),
+        }
+    }
+}
+
+impl<'a> Future for Shutdown<'a> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Some(rt) = self.rt.upgrade() else {
+            return Poll::Ready(());
+        };
+        if rt.shutting_down.get() {
+            return Poll::Ready(());
+        }
+
+        // 记录等待关闭的任务，事件循环在退出前会等待这些任务完成
+        if let Some(current) = rt.current.borrow().as_ref() {
+            rt.shutdown_tasks.borrow_mut().insert(current.id);
+        }
+        let mut waiters = rt.shutdown_waiters.borrow_mut();
+        if !waiters.iter().any(|w| w.will_wake(cx.waker())) {
+            waiters.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// Error returned by a `JoinHandle` when the task did not produce an output.
+#[derive(Debug)]
+pub enum JoinError {
+    /// The task was cancelled, or dropped together with its runtime, before it completed. A
+    /// `spawn_blocking` closure is cancelled if no pool thread can be started to run it.
+    Cancelled,
+    /// The task panicked and the panic was caught by the runtime.
+    Panicked,
+}
+
+impl Error for JoinError {}
+
+impl Display for JoinError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Cancelled => write!(f, "JoinError: task was cancelled"),
+            Self::Panicked => write!(f, "JoinError: task panicked"),
+        }
+    }
+}
+
+/// The output slot shared between a task and its `JoinHandle`.
+type JoinSlot<T> = Rc<RefCell<(Option<T>, Option<Waker>)>>;
+
+/// Wakes the `JoinHandle` when the task finishes or is dropped without finishing.
+struct JoinNotifier<T>(JoinSlot<T>);
+
+impl<T> Drop for JoinNotifier<T> {
+    fn drop(&mut self) {
+        if let Some(waker) = self.0.borrow_mut().1.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// A handle to a spawned task that resolves to the task's output.
+///
+/// Awaiting the handle yields `Ok(output)` once the task completes, or
+/// `Err(JoinError::Cancelled)` if the task was cancelled or dropped first.
+/// Dropping the handle detaches the task; it keeps running to completion.
+pub struct JoinHandle<T> {
+    cancellable: Cancellable,
+    slot: JoinSlot<T>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Cancels the task, see `Cancellable::cancel`.
+    pub fn cancel(&self) {
+        self.cancellable.cancel();
+    }
+
+    /// Checks if the task has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellable.is_cancelled()
+    }
+
+    /// Returns the cancellation handle of the task.
+    pub fn cancellable(&self) -> &Cancellable {
+        &self.cancellable
+    }
 }
 
-impl<'a, R> Runtime<'a, R> {
-    pub fn new() -> Self {
+impl<T> Default for JoinHandle<T> {
+    /// Creates a handle that is not attached to any task and resolves to `JoinError::Cancelled`.
+    fn default() -> Self {
         Self {
-            context: Context::from_waker(Waker::noop()).into(),
-            pendings: Vec::default().into(),
-            new_pendings: Vec::default().into(),
-            polling: Cell::new(false),
+            cancellable: Default::default(),
+            slot: Rc::new(RefCell::new((None, None))),
+        }
+    }
+}
+
+impl<T> From<JoinHandle<T>> for Cancellable {
+    fn from(value: JoinHandle<T>) -> Self {
+        value.cancellable
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut slot = this.slot.borrow_mut();
+        if let Some(output) = slot.0.take() {
+            Poll::Ready(Ok(output))
+        } else if this.cancellable.state.panicked.get() {
+            Poll::Ready(Err(JoinError::Panicked))
+        } else if this.cancellable.is_cancelled() || Rc::strong_count(&this.slot) == 1 {
+            // 任务已被取消，或者任务已经被丢弃而没有产生输出
+            Poll::Ready(Err(JoinError::Cancelled))
+        } else {
+            slot.1.replace(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// The ready queue shared between a runtime, the wakers of its tasks and loop proxies.
+///
+/// Wakers may be invoked from any thread, so the queue is guarded by a mutex and
+/// waking a task also unparks the thread that owns the runtime.
+pub(crate) struct Scheduler {
+    ready: Mutex<Vec<usize>>,
+    // 已被取消、等待运行时丢弃的任务
+    cancelled: Mutex<Vec<usize>>,
+    // 其他线程通过 LoopProxy 投递的闭包和消息
+    posted: Mutex<Vec<Posted>>,
+    quit: AtomicBool,
+    // Linux 上运行时阻塞在 epoll 中，通过 eventfd 唤醒；eventfd 在创建运行时时才创建，
+    // 在此之前没有需要唤醒的线程
+    #[cfg(target_os = "linux")]
+    notifier: OnceLock<Arc<Notifier>>,
+    #[cfg(not(target_os = "linux"))]
+    thread: Thread,
+}
+
+impl Scheduler {
+    /// Creates a scheduler owned by the current thread.
+    pub(crate) fn new() -> Self {
+        Self {
+            ready: Default::default(),
+            cancelled: Default::default(),
+            posted: Default::default(),
+            quit: Default::default(),
+            #[cfg(target_os = "linux")]
+            notifier: Default::default(),
+            #[cfg(not(target_os = "linux"))]
+            thread: thread::current(),
+        }
+    }
+
+    /// Returns the eventfd waking the runtime, creating it on first use.
+    #[cfg(target_os = "linux")]
+    fn notifier(&self) -> IoResult<Arc<Notifier>> {
+        if let Some(notifier) = self.notifier.get() {
+            return Ok(notifier.clone());
+        }
+        let notifier = Arc::new(Notifier::new()?);
+        Ok(self.notifier.get_or_init(|| notifier).clone())
+    }
+
+    fn schedule(&self, id: usize) {
+        self.ready.lock().unwrap().push(id);
+        self.unpark();
+    }
+
+    pub(crate) fn post(&self, posted: Posted) {
+        self.posted.lock().unwrap().push(posted);
+        self.unpark();
+    }
+
+    /// Wakes the thread owning the runtime if it is parked.
+    pub(crate) fn unpark(&self) {
+        #[cfg(target_os = "linux")]
+        if let Some(notifier) = self.notifier.get() {
+            notifier.notify();
+        }
+        #[cfg(not(target_os = "linux"))]
+        self.thread.unpark();
+    }
+
+    pub(crate) fn request_quit(&self) {
+        self.quit.store(true, Ordering::Release);
+        self.unpark();
+    }
+
+    /// Returns `true` if a loop proxy asked the loop to quit.
+    pub(crate) fn quit_requested(&self) -> bool {
+        self.quit.load(Ordering::Acquire)
+    }
+
+    fn cancel(&self, id: usize) {
+        self.cancelled.lock().unwrap().push(id);
+        self.unpark();
+    }
+
+    fn take_ready(&self) -> Vec<usize> {
+        take(&mut *self.ready.lock().unwrap())
+    }
+
+    fn take_cancelled(&self) -> Vec<usize> {
+        take(&mut *self.cancelled.lock().unwrap())
+    }
+
+    fn take_posted(&self) -> Vec<Posted> {
+        take(&mut *self.posted.lock().unwrap())
+    }
+}
+
+/// The waker of a single task, pushing the task onto the ready queue when woken.
+struct TaskWaker {
+    id: usize,
+    // 防止同一个任务在一次轮询前被重复加入就绪队列
+    queued: AtomicBool,
+    scheduler: Arc<Scheduler>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.queued.swap(true, Ordering::AcqRel) {
+            self.scheduler.schedule(self.id);
+        }
+    }
+}
+
+/// A handler receiving the failures of tasks.
+type FailureHandler<'a> = Rc<dyn Fn(Failure) + 'a>;
+
+/// A handler receiving the messages of one type sent through a `LoopProxy`.
+type MessageHandler<'a> = Rc<dyn Fn(Box<dyn Any>) + 'a>;
+
+/// A pending update of a component, keyed by the address of the component.
+type DirtyUpdate<'a> = (usize, Box<dyn FnOnce() + 'a>);
+
+/// An error boundary: catches the failures of every task spawned inside it.
+struct Boundary<'a> {
+    handler: FailureHandler<'a>,
+    // 外层的错误边界，边界自身重新渲染时的任务归属于它
+    parent: Option<Rc<Boundary<'a>>>,
+}
+
+/// The task being polled.
+struct Current<'a> {
+    id: usize,
+    daemon: bool,
+    cancellable: Cancellable,
+    boundary: Option<Rc<Boundary<'a>>>,
+}
+
+struct Task<'a> {
+    name: Option<&'static str>,
+    // 守护任务不会阻止 `Loop::exit_when_done` 退出事件循环
+    daemon: bool,
+    // 接收该任务失败的错误边界
+    boundary: Option<Rc<Boundary<'a>>>,
+    // 该任务 spawn 的新任务所继承的错误边界
+    scope: Option<Rc<Boundary<'a>>>,
+    cancellable: Cancellable,
+    future: LocalBoxFuture<'a, ()>,
+    waker: Arc<TaskWaker>,
+}
+
+//noinspection SpellCheckingInspection
+pub struct Runtime<'a> {
+    tasks: RefCell<HashMap<usize, Task<'a>>>,
+    next_id: Cell<usize>,
+    // 正在被轮询的任务（轮询期间它会被临时移出 tasks）
+    current: RefCell<Option<Current<'a>>>,
+    // 新 spawn 的任务所继承的错误边界
+    scope: RefCell<Option<Rc<Boundary<'a>>>>,
+    scheduler: Arc<Scheduler>,
+    timer: Rc<Timer>,
+    #[cfg(target_os = "linux")]
+    reactor: Rc<Reactor>,
+    failure_handler: RefCell<Option<FailureHandler<'a>>>,
+    catch_panics: Cell<bool>,
+    message_handlers: RefCell<HashMap<TypeId, MessageHandler<'a>>>,
+    shutting_down: Cell<bool>,
+    shutdown_waiters: RefCell<Vec<Waker>>,
+    // 等待过关闭信号的任务
+    shutdown_tasks: RefCell<HashSet<usize>>,
+    // 在本轮轮询结束时（或 batch 结束时）更新的组件
+    dirty: RefCell<Vec<DirtyUpdate<'a>>>,
+    batching: Cell<usize>,
+    pub(crate) reactions: Rc<Reactions<'a>>,
+}
+
+impl<'a> Runtime<'a> {
+    /// Creates a runtime owned by the current thread.
+    ///
+    /// Wakers of spawned tasks unpark this thread when they are woken. Fails if the operating
+    /// system resources used to park the thread (an epoll instance and an eventfd on Linux)
+    /// cannot be created.
+    pub fn new() -> IoResult<Self> {
+        Self::with_timer(Default::default())
+    }
+
+    pub(crate) fn with_timer(timer: Rc<Timer>) -> IoResult<Self> {
+        Self::with_parts(Arc::new(Scheduler::new()), timer)
+    }
+
+    pub(crate) fn with_parts(scheduler: Arc<Scheduler>, timer: Rc<Timer>) -> IoResult<Self> {
+        Ok(Self {
+            tasks: Default::default(),
+            next_id: Default::default(),
+            current: Default::default(),
+            scope: Default::default(),
+            #[cfg(target_os = "linux")]
+            reactor: Rc::new(Reactor::new(scheduler.notifier()?)?),
+            scheduler,
+            timer,
+            failure_handler: Default::default(),
+            catch_panics: Default::default(),
+            message_handlers: Default::default(),
+            shutting_down: Default::default(),
+            shutdown_waiters: Default::default(),
+            shutdown_tasks: Default::default(),
+            dirty: Default::default(),
+            batching: Default::default(),
+            reactions: Default::default(),
+        })
+    }
+
+    /// Returns a proxy for posting work into this runtime from other threads.
+    pub fn proxy(&self) -> LoopProxy {
+        LoopProxy::new(self.scheduler.clone())
+    }
+
+    /// Sets the handler receiving messages of type `M` sent with `LoopProxy::send`.
+    ///
+    /// The handler is called on the thread owning the runtime, at the start of `poll_all`.
+    /// To hand messages to a component, emit them to an `EventListener`:
+    ///
+    /// ```
+    /// use compo::prelude::*;
+    ///
+    /// let rt = Runtime::new().unwrap();
+    /// let listener = EventListener::<String>::default();
+    /// let emitter = listener.new_emitter();
+    /// rt.on_message(move |m: String| {
+    ///     let _ = emitter.emit(m);
+    /// });
+    /// ```
+    pub fn on_message<M, F>(&self, handler: F)
+    where
+        M: 'static,
+        F: Fn(M) + 'a,
+    {
+        let handler = move |message: Box<dyn Any>| {
+            if let Ok(message) = message.downcast::<M>() {
+                handler(*message);
+            }
+        };
+        self.message_handlers
+            .borrow_mut()
+            .insert(TypeId::of::<M>(), Rc::new(handler));
+    }
+
+    /// Delivers the closures and messages posted through loop proxies.
+    fn dispatch_posted(&self) {
+        for posted in self.scheduler.take_posted() {
+            match posted {
+                Posted::Call(f) => f(),
+                Posted::Message(message) => {
+                    let handler = self
+                        .message_handlers
+                        .borrow()
+                        .get(&(*message).type_id())
+                        .cloned();
+                    if let Some(handler) = handler {
+                        handler(message);
+                    }
+                }
+            }
         }
     }
 
     /// Spawn a future and return a JoinHandle to await its completion
-    pub fn spawn<Fut>(&self, fut: Fut) -> Cancellable
+    ///
+    /// The future is polled on the next call to `poll_all`, and afterwards only when its
+    /// waker is woken.
+    pub fn spawn<Fut>(&self, fut: Fut) -> JoinHandle<Fut::Output>
+    where
+        Fut: Future + 'a,
+        Fut::Output: 'a,
+    {
+        self.spawn_task(None, false, fut)
+    }
+
+    /// Spawn a background future that does not keep the event loop alive.
+    ///
+    /// Daemon tasks are not counted by `active_count`, so a loop running with
+    /// `Loop::exit_when_done` exits once only daemon tasks are left, dropping them.
+    pub fn spawn_daemon<Fut>(&self, fut: Fut) -> JoinHandle<Fut::Output>
+    where
+        Fut: Future + 'a,
+        Fut::Output: 'a,
+    {
+        self.spawn_task(None, true, fut)
+    }
+
+    /// Spawn a future on behalf of the component called `name`.
+    ///
+    /// The name is reported to the panic handler if the task panics.
+    pub fn spawn_named<Fut>(&self, name: &'static str, fut: Fut) -> JoinHandle<Fut::Output>
+    where
+        Fut: Future + 'a,
+        Fut::Output: 'a,
+    {
+        self.spawn_task(Some(name), false, fut)
+    }
+
+    pub(crate) fn spawn_task<Fut>(
+        &self,
+        name: Option<&'static str>,
+        daemon: bool,
+        fut: Fut,
+    ) -> JoinHandle<Fut::Output>
     where
-        Fut: Future<Output=R> + 'a,
+        Fut: Future + 'a,
+        Fut::Output: 'a,
     {
-        let handle = Cancellable::new();
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        let waker = Arc::new(TaskWaker {
+            id,
+            queued: false.into(),
+            scheduler: self.scheduler.clone(),
+        });
+        let cancellable = Cancellable::new(Some((id, Arc::downgrade(&self.scheduler))));
+        let slot = JoinSlot::default();
+        let notifier = JoinNotifier(slot.clone());
+        let boundary = self.scope.borrow().clone();
         let task = Task {
-            cancellable: handle.clone(),
-            future: fut.boxed_local(),
+            name,
+            daemon,
+            boundary: boundary.clone(),
+            scope: boundary,
+            cancellable: cancellable.clone(),
+            future: async move {
+                let output = fut.await;
+                notifier.0.borrow_mut().0 = Some(output);
+            }
+            .boxed_local(),
+            waker: waker.clone(),
         };
+        self.tasks.borrow_mut().insert(id, task);
+        waker.wake_by_ref();
 
-        // 如果当前正在执行 poll_all，将新的 future 添加到 new_pendings
-        // 否则直接添加到 pendings
-        if self.polling.get() {
-            self.new_pendings.borrow_mut().push(task);
+        JoinHandle { cancellable, slot }
+    }
+
+    /// Sets the handler receiving failures that are not caught by an error boundary:
+    /// errors returned by fallible component bodies and, with `catch_panics`, panics.
+    ///
+    /// Without a handler, such failures are printed to stderr.
+    pub fn on_failure<F>(&self, handler: F)
+    where
+        F: Fn(Failure) + 'a,
+    {
+        self.failure_handler.replace(Some(Rc::new(handler)));
+    }
+
+    /// Enables panic isolation: every task poll is wrapped in `catch_unwind`.
+    ///
+    /// A task that panics is removed from the runtime and `handler` receives the panic
+    /// payload together with the name of the component owning the task. `handler` becomes
+    /// the failure handler, see `on_failure`. Without panic isolation (the default), a panic
+    /// unwinds through `poll_all`.
+    pub fn catch_panics<F>(&self, handler: F)
+    where
+        F: Fn(Failure) + 'a,
+    {
+        self.on_failure(handler);
+        self.catch_panics.set(true);
+    }
+
+    /// Turns the task currently being polled into an error boundary.
+    ///
+    /// Every task spawned by the current task from now on (and, transitively, every task
+    /// those tasks spawn) reports its failures to `handler` instead of the panic handler of
+    /// the runtime. Panics of such tasks are always caught, even if `catch_panics` was not
+    /// called. While `handler` runs, newly spawned tasks belong to the enclosing boundary,
+    /// so the boundary can re-render itself from the handler.
+    pub fn catch_failures<F>(&self, handler: F)
+    where
+        F: Fn(Failure) + 'a,
+    {
+        let parent = self
+            .current
+            .borrow()
+            .as_ref()
+            .and_then(|c| c.boundary.clone());
+        self.scope.replace(Some(Rc::new(Boundary {
+            handler: Rc::new(handler),
+            parent,
+        })));
+    }
+
+    /// Reports a failure of the task currently being polled to its error boundary, or to
+    /// the failure handler if the task is not inside an error boundary.
+    pub fn report(&self, failure: Failure) {
+        let boundary = self
+            .current
+            .borrow()
+            .as_ref()
+            .and_then(|c| c.boundary.clone());
+        self.deliver(boundary, failure);
+    }
+
+    /// Delivers a failure to `boundary`, or to the failure handler if there is no boundary.
+    fn deliver(&self, boundary: Option<Rc<Boundary<'a>>>, failure: Failure) {
+        if let Some(boundary) = boundary {
+            let previous = self.scope.replace(boundary.parent.clone());
+            (boundary.handler)(failure);
+            self.scope.replace(previous);
+        } else if let Some(handler) = self.failure_handler.borrow().clone() {
+            handler(failure);
         } else {
-            self.pendings.borrow_mut().push(task);
+            eprintln!("{}", failure);
         }
-
-        handle
     }
 
+    /// Returns the number of tasks that have not completed yet.
     pub fn count(&self) -> usize {
-        self.pendings.borrow().len() + self.new_pendings.borrow().len()
+        self.tasks.borrow().len() + self.current.borrow().as_ref().map_or(0, |_| 1)
+    }
+
+    /// Returns the number of tasks that have not completed yet, not counting daemon tasks.
+    pub fn active_count(&self) -> usize {
+        let tasks = self.tasks.borrow().values().filter(|t| !t.daemon).count();
+        tasks
+            + self
+                .current
+                .borrow()
+                .as_ref()
+                .map_or(0, |c| !c.daemon as usize)
+    }
+
+    /// Returns `true` if at least one task has been woken and is waiting to be polled,
+    /// a cancelled task is waiting to be dropped, or work posted through a proxy is waiting
+    /// to be delivered.
+    pub fn has_ready(&self) -> bool {
+        !self.scheduler.ready.lock().unwrap().is_empty()
+            || !self.scheduler.cancelled.lock().unwrap().is_empty()
+            || !self.scheduler.posted.lock().unwrap().is_empty()
+            || !self.dirty.borrow().is_empty()
+            || self.reactions.has_changed()
+    }
+
+    /// Returns the cancellation handle of the task currently being polled.
+    ///
+    /// Component bodies can use it to stop helper futures once the body itself is
+    /// cancelled, for example when the component re-renders:
+    ///
+    /// ```
+    /// use compo::prelude::*;
+    ///
+    /// async fn body(rt: &Runtime<'_>) {
+    ///     let cancellable = rt.current_cancellable().unwrap_or_default();
+    ///     rt.spawn(async move {
+    ///         let work = async { sleep(Duration::from_secs(60)).await };
+    ///         futures_util::future::select(Box::pin(work), cancellable.cancelled()).await;
+    ///     });
+    /// }
+    /// ```
+    pub fn current_cancellable(&self) -> Option<Cancellable> {
+        self.current
+            .borrow()
+            .as_ref()
+            .map(|c| c.cancellable.clone())
+    }
+
+    /// Returns a future that resolves when the runtime begins shutting down.
+    ///
+    /// A task that awaits it gets a chance to clean up (flush files, close connections) when
+    /// the loop quits: the loop keeps running until every such task has completed, or until
+    /// the shutdown timeout passes (see `Loop::shutdown_timeout`). The future resolves
+    /// immediately if the runtime has been dropped.
+    pub fn on_shutdown(self: &Rc<Self>) -> Shutdown<'a> {
+        Shutdown {
+            rt: Rc::downgrade(self),
+        }
+    }
+
+    /// Begins shutting down: wakes every task awaiting `on_shutdown`.
+    pub fn shutdown(&self) {
+        if self.shutting_down.replace(true) {
+            return;
+        }
+
+        for waker in self.shutdown_waiters.take() {
+            waker.wake();
+        }
+    }
+
+    /// Returns `true` once `shutdown` has been called.
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.get()
+    }
+
+    /// Returns `true` while a task that awaited `on_shutdown` has not completed yet.
+    pub fn has_shutdown_tasks(&self) -> bool {
+        let tasks = self.tasks.borrow();
+        let current = self.current.borrow().as_ref().map(|c| c.id);
+        let mut shutdown_tasks = self.shutdown_tasks.borrow_mut();
+        shutdown_tasks.retain(|id| tasks.contains_key(id) || current == Some(*id));
+        !shutdown_tasks.is_empty()
+    }
+
+    /// Schedules `update` to run once at the end of the current `poll_all`, or of the current
+    /// `batch`. Nothing is scheduled if an update with the same `key` is already pending.
+    pub(crate) fn schedule_update(&self, key: usize, update: Box<dyn FnOnce() + 'a>) {
+        let mut dirty = self.dirty.borrow_mut();
+        if !dirty.iter().any(|(k, _)| *k == key) {
+            dirty.push((key, update));
+        }
+    }
+
+    /// Runs `f`, deferring the updates of components until it returns, so a component
+    /// updated several times in `f` re-renders only once.
+    ///
+    /// ```
+    /// use compo::prelude::*;
+    ///
+    /// #[component]
+    /// async fn counter(count: u32) {
+    ///     println!("{}", count);
+    /// }
+    ///
+    /// let rt = TestRuntime::new();
+    /// let c = rt.mount(counter);
+    /// rt.runtime().batch(|| {
+    ///     c.set_count(&1);
+    ///     c.update();
+    ///     c.set_count(&2);
+    ///     c.update();
+    /// });
+    /// rt.run_until_stalled();
+    /// ```
+    pub fn batch<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.batching.set(self.batching.get() + 1);
+        let output = f();
+        self.batching.set(self.batching.get() - 1);
+        if self.batching.get() == 0 {
+            self.flush_updates();
+        }
+
+        output
+    }
+
+    /// Returns `true` while a `batch` is running.
+    pub fn is_batching(&self) -> bool {
+        self.batching.get() > 0
+    }
+
+    /// Creates an effect running `f` now, and again at the end of every tick in which a
+    /// `Signal` or `Memo` read by its previous run changed. Dropping the effect stops it.
+    pub fn effect(&self, f: impl Fn() + 'a) -> Effect<'a> {
+        self.reactions.effect(f)
+    }
+
+    /// Runs the reactions to changed signals and the pending updates of components, including
+    /// those caused by the updates.
+    fn flush_updates(&self) {
+        loop {
+            self.reactions.run_changed();
+            let dirty = self.dirty.take();
+            if dirty.is_empty() {
+                break;
+            }
+
+            for (_, update) in dirty {
+                update();
+            }
+        }
+    }
+
+    /// Drops every cancelled task without polling it again.
+    fn drop_cancelled(&self) {
+        loop {
+            let cancelled = self.scheduler.take_cancelled();
+            if cancelled.is_empty() {
+                break;
+            }
+
+            for id in cancelled {
+                // 在借用释放之后再丢弃任务，任务的析构过程中可能会取消其他任务
+                let task = self.tasks.borrow_mut().remove(&id);
+                drop(task);
+            }
+        }
     }
 
     //noinspection SpellCheckingInspection
+    /// Polls every task that has been woken since the previous call, including tasks whose
+    /// `sleep` deadline has passed.
+    ///
+    /// Tasks woken (or spawned) while this method is running are polled on the next call.
     pub fn poll_all(&self) {
-        // 标记开始执行 poll_all
-        self.polling.set(true);
+        let _timer = self.timer.enter();
+        #[cfg(target_os = "linux")]
+        let _reactor = self.reactor.enter();
+        self.timer.fire();
+        self.dispatch_posted();
+        self.drop_cancelled();
+        for id in self.scheduler.take_ready() {
+            // 先将任务移出，这样任务在轮询期间也可以 spawn 新的任务
+            let Some(mut task) = self.tasks.borrow_mut().remove(&id) else {
+                continue;
+            };
+            task.waker.queued.store(false, Ordering::Release);
+            let waker = Waker::from(task.waker.clone());
+            let mut cx = Context::from_waker(&waker);
+            self.current.replace(Some(Current {
+                id,
+                daemon: task.daemon,
+                cancellable: task.cancellable.clone(),
+                boundary: task.boundary.clone(),
+            }));
+            self.scope.replace(task.scope.clone());
+            // 处于错误边界内的任务总是捕获 panic
+            let catching = task.boundary.is_some() || self.catch_panics.get();
+            let poll = if catching {
+                catch_unwind(AssertUnwindSafe(|| task.future.as_mut().poll(&mut cx)))
+            } else {
+                Ok(task.future.as_mut().poll(&mut cx))
+            };
+            self.current.take();
+            task.scope = self.scope.take();
+            match poll {
+                Ok(poll) if poll.is_pending() && !task.cancellable.is_cancelled() => {
+                    self.tasks.borrow_mut().insert(id, task);
+                }
+                Ok(_) => drop(task),
+                Err(payload) => {
+                    task.cancellable.state.panicked.set(true);
+                    let name = task.name;
+                    let boundary = task.boundary.clone();
+                    // 丢弃已经 panic 的任务时也可能再次 panic
+                    let _ = catch_unwind(AssertUnwindSafe(|| drop(task)));
+                    self.deliver(boundary, Failure::panic(name, payload));
+                }
+            }
+            // 本次轮询中被取消的任务不会再有机会被轮询
+            self.drop_cancelled();
+        }
+        // 本轮中被修改的组件只更新一次
+        self.flush_updates();
+    }
+
+    /// Blocks the current thread until a task is woken, the next timer deadline is reached,
+    /// a file descriptor registered with `AsyncFd` becomes ready (on Linux) or the optional
+    /// `timeout` elapses.
+    ///
+    /// Returns immediately if a task is already waiting to be polled. Must be called from the
+    /// thread that created the runtime. Fails if waiting on the file descriptors failed.
+    pub fn park(&self, timeout: Option<Duration>) -> IoResult<()> {
+        let deadline = self
+            .timer
+            .next_deadline()
+            .map(|d| d.saturating_duration_since(self.timer.now()));
+        let timeout = match (deadline, timeout) {
+            (Some(d), Some(t)) => Some(d.min(t)),
+            (d, t) => d.or(t),
+        };
 
-        // 处理当前的 pendings
+        #[cfg(target_os = "linux")]
         {
-            let mut pendings = self.pendings.borrow_mut();
-            pendings.retain_mut(|task| {
-                task.future
-                    .as_mut()
-                    .poll(&mut self.context.borrow_mut())
-                    .is_pending()
-                    && !task.cancellable.is_cancelled()
-            });
+            // 即使已有任务就绪，也要检查一次文件描述符，避免 I/O 任务被饿死
+            let timeout = if self.has_ready() {
+                Some(Duration::ZERO)
+            } else {
+                timeout
+            };
+            self.reactor.wait(timeout)
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            if self.has_ready() {
+                return Ok(());
+            }
+            match timeout {
+                Some(t) => thread::park_timeout(t),
+                None => thread::park(),
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+impl<'a> Runtime<'a> {
+    /// Polls and parks the runtime until every task completed, returning the number of polls.
+    pub(crate) fn run_until_done(&self) -> usize {
+        let mut polls = 0;
+        loop {
+            self.poll_all();
+            polls += 1;
+            if self.count() == 0 {
+                return polls;
+            }
+            self.park(None).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        crate::runtime::{JoinError, Runtime},
+        std::{cell::Cell, future::pending, future::poll_fn, rc::Rc, task::Poll},
+    };
+
+    #[test]
+    fn test_runtime_polls_only_woken_tasks() {
+        let rt = Runtime::new().unwrap();
+        let polls = Rc::new(Cell::new(0));
+        let polls2 = polls.clone();
+        rt.spawn(poll_fn(move |_| {
+            polls2.set(polls2.get() + 1);
+            Poll::<()>::Pending
+        }));
+
+        rt.poll_all();
+        rt.poll_all();
+        assert_eq!(polls.get(), 1);
+        assert_eq!(rt.count(), 1);
+    }
+
+    #[test]
+    fn test_runtime_wake_reschedules_task() {
+        let rt = Runtime::new().unwrap();
+        let polls = Rc::new(Cell::new(0));
+        let polls2 = polls.clone();
+        rt.spawn(poll_fn(move |cx| {
+            polls2.set(polls2.get() + 1);
+            if polls2.get() < 3 {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        }));
+
+        while rt.has_ready() {
+            rt.poll_all();
+        }
+        assert_eq!(polls.get(), 3);
+        assert_eq!(rt.count(), 0);
+    }
+
+    #[test]
+    fn test_join_handle_output() {
+        let rt = Runtime::new().unwrap();
+        let handle = rt.spawn(async { 42 });
+        let result = Rc::new(Cell::new(None));
+        let result2 = result.clone();
+        rt.spawn(async move { result2.set(Some(handle.await.ok())) });
 
-            // 将 new_pendings 中的 futures 移动到 pendings
-            let mut new_pendings = self.new_pendings.borrow_mut();
-            pendings.append(&mut new_pendings);
+        while rt.has_ready() {
+            rt.poll_all();
         }
+        assert_eq!(result.get(), Some(Some(42)));
+    }
+
+    #[test]
+    fn test_join_handle_cancelled() {
+        let rt = Runtime::new().unwrap();
+        let handle = rt.spawn(pending::<()>());
+        let cancellable = handle.cancellable().clone();
+        let result = Rc::new(Cell::new(None));
+        let result2 = result.clone();
+        rt.spawn(
+            async move { result2.set(Some(matches!(handle.await, Err(JoinError::Cancelled)))) },
+        );
+
+        rt.poll_all();
+        cancellable.cancel();
+        while rt.has_ready() {
+            rt.poll_all();
+        }
+        assert_eq!(result.get(), Some(true));
+        assert_eq!(rt.count(), 0);
+    }
+
+    #[test]
+    fn test_cancelled_task_is_dropped_without_polling() {
+        let rt = Runtime::new().unwrap();
+        let polls = Rc::new(Cell::new(0));
+        let polls2 = polls.clone();
+        let handle = rt.spawn(poll_fn(move |cx| {
+            polls2.set(polls2.get() + 1);
+            cx.waker().wake_by_ref();
+            Poll::<()>::Pending
+        }));
+        let cancellable = handle.cancellable().clone();
+        let notified = Rc::new(Cell::new(false));
+        let notified2 = notified.clone();
+        rt.spawn(async move {
+            cancellable.cancelled().await;
+            notified2.set(true);
+        });
+
+        rt.poll_all();
+        handle.cancel();
+        rt.poll_all();
+        rt.poll_all();
+        assert_eq!(polls.get(), 1);
+        assert!(notified.get());
+        assert_eq!(rt.count(), 0);
+    }
 
-        // 标记结束执行 poll_all
-        self.polling.set(false);
+    #[test]
+    fn test_panic_is_isolated_to_task() {
+        let rt = Runtime::new().unwrap();
+        let failures = Rc::new(Cell::new(0));
+        let failures2 = failures.clone();
+        rt.catch_panics(move |f| {
+            assert_eq!(f.name(), Some("faulty"));
+            assert_eq!(f.message(), Some("boom"));
+            failures2.set(failures2.get() + 1);
+        });
+        let faulty = rt.spawn_named("faulty", async { panic!("boom") });
+        let healthy = rt.spawn(async { 1 });
+        let result = Rc::new(Cell::new(None));
+        let result2 = result.clone();
+        rt.spawn(async move {
+            result2.set(Some((
+                matches!(faulty.await, Err(JoinError::Panicked)),
+                healthy.await.ok(),
+            )))
+        });
+
+        while rt.has_ready() {
+            rt.poll_all();
+        }
+        assert_eq!(failures.get(), 1);
+        assert_eq!(result.get(), Some((true, Some(1))));
+    }
+
+    #[test]
+    fn test_boundary_catches_descendant_failure() {
+        let rt = Rc::new(Runtime::new().unwrap());
+        let caught = Rc::new(Cell::new(None));
+        let caught2 = caught.clone();
+        let rt2 = rt.clone();
+        rt.spawn(async move {
+            rt2.catch_failures(move |f| caught2.set(f.name()));
+            let rt3 = rt2.clone();
+            rt2.spawn(async move {
+                rt3.spawn_named("child", async { panic!("boom") });
+            });
+        });
+
+        while rt.has_ready() {
+            rt.poll_all();
+        }
+        assert_eq!(caught.get(), Some("child"));
     }
 }
@@ -0,0 +1,303 @@
+use {
+    libc::{
+        O_CLOEXEC, O_NONBLOCK, SA_RESTART, SIGHUP, SIGINT, SIGTERM, SIGUSR1, SIGUSR2, SIGWINCH,
+        c_int, sigaction, sighandler_t,
+    },
+    std::{
+        fmt::{Debug, Formatter, Result as FmtResult},
+        future::poll_fn,
+        io::{Error, Result as IoResult},
+        mem::zeroed,
+        sync::{
+            Arc, Mutex, OnceLock, Weak,
+            atomic::{AtomicI32, AtomicUsize, Ordering},
+        },
+        task::{Poll, Waker},
+        thread,
+    },
+};
+
+/// The write end of the self-pipe, written by the signal handler.
+static WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn handle_signal(signum: c_int) {
+    let fd = WRITE_FD.load(Ordering::Relaxed);
+    if fd >= 0 {
+        // 信号处理函数中只能调用异步信号安全的函数，并且不能改变 errno
+        let errno = unsafe { *libc::__errno_location() };
+        let byte = signum as u8;
+        unsafe {
+            libc::write(fd, (&byte as *const u8).cast(), 1);
+            *libc::__errno_location() = errno;
+        }
+    }
+}
+
+/// A kind of POSIX signal.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SignalKind(c_int);
+
+impl SignalKind {
+    /// `SIGINT`, sent by Ctrl-C in a terminal.
+    pub const INTERRUPT: Self = Self(SIGINT);
+    /// `SIGTERM`, the polite request to terminate.
+    pub const TERMINATE: Self = Self(SIGTERM);
+    /// `SIGHUP`, sent when the controlling terminal is closed.
+    pub const HANGUP: Self = Self(SIGHUP);
+    /// `SIGWINCH`, sent when the terminal window is resized.
+    pub const WINDOW_CHANGE: Self = Self(SIGWINCH);
+    /// `SIGUSR1`.
+    pub const USER_DEFINED1: Self = Self(SIGUSR1);
+    /// `SIGUSR2`.
+    pub const USER_DEFINED2: Self = Self(SIGUSR2);
+
+    /// Creates a signal kind from a raw signal number.
+    pub const fn from_raw(signum: c_int) -> Self {
+        Self(signum)
+    }
+
+    /// Returns the raw signal number.
+    pub const fn as_raw(&self) -> c_int {
+        self.0
+    }
+}
+
+impl Debug for SignalKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "SignalKind({})", self.0)
+    }
+}
+
+/// The deliveries of one signal to one listener.
+struct Receiver {
+    kind: SignalKind,
+    pending: AtomicUsize,
+    waker: Mutex<Option<Waker>>,
+}
+
+/// Reads the self-pipe on a background thread and dispatches the signals to the listeners.
+struct Driver {
+    receivers: Mutex<Vec<Weak<Receiver>>>,
+    // 安装处理函数之前的信号处理方式，最后一个监听者被丢弃后恢复
+    previous: Mutex<Vec<(SignalKind, sigaction)>>,
+}
+
+impl Driver {
+    fn get() -> IoResult<&'static Self> {
+        static DRIVER: OnceLock<Result<Driver, i32>> = OnceLock::new();
+        match DRIVER.get_or_init(Self::start) {
+            Ok(driver) => Ok(driver),
+            Err(errno) => Err(Error::from_raw_os_error(*errno)),
+        }
+    }
+
+    fn start() -> Result<Self, i32> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), O_CLOEXEC) } < 0 {
+            return Err(Error::last_os_error().raw_os_error().unwrap_or_default());
+        }
+        // 写端必须是非阻塞的，管道写满时信号处理函数不能被阻塞
+        unsafe { libc::fcntl(fds[1], libc::F_SETFL, O_NONBLOCK) };
+        WRITE_FD.store(fds[1], Ordering::Relaxed);
+        let read_fd = fds[0];
+        thread::Builder::new()
+            .name("compo-signal".into())
+            .spawn(move || {
+                let mut byte = 0u8;
+                loop {
+                    let n = unsafe { libc::read(read_fd, (&mut byte as *mut u8).cast(), 1) };
+                    if n == 1 {
+                        Self::get().unwrap().dispatch(SignalKind(byte as _));
+                    } else if n == 0 {
+                        break;
+                    }
+                }
+            })
+            .map_err(|e| e.raw_os_error().unwrap_or_default())?;
+
+        Ok(Self {
+            receivers: Default::default(),
+            previous: Default::default(),
+        })
+    }
+
+    fn dispatch(&self, kind: SignalKind) {
+        let receivers = self.receivers.lock().unwrap();
+        for receiver in receivers.iter().filter_map(Weak::upgrade) {
+            if receiver.kind == kind {
+                receiver.pending.fetch_add(1, Ordering::AcqRel);
+                if let Some(waker) = receiver.waker.lock().unwrap().take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+
+    fn register(&self, kind: SignalKind) -> IoResult<Arc<Receiver>> {
+        let mut receivers = self.receivers.lock().unwrap();
+        let installed = receivers
+            .iter()
+            .filter_map(Weak::upgrade)
+            .any(|r| r.kind == kind);
+        if !installed {
+            let mut action: sigaction = unsafe { zeroed() };
+            action.sa_sigaction = handle_signal as *const () as sighandler_t;
+            action.sa_flags = SA_RESTART;
+            let previous = set_action(kind, &action)?;
+            self.previous.lock().unwrap().push((kind, previous));
+        }
+        let receiver = Arc::new(Receiver {
+            kind,
+            pending: Default::default(),
+            waker: Default::default(),
+        });
+        receivers.push(Arc::downgrade(&receiver));
+
+        Ok(receiver)
+    }
+
+    fn unregister(&self, receiver: &Arc<Receiver>) {
+        let mut receivers = self.receivers.lock().unwrap();
+        receivers.retain(|r| r.strong_count() > 0 && !r.ptr_eq(&Arc::downgrade(receiver)));
+        // 最后一个监听者被丢弃后恢复信号原来的处理方式（默认行为、忽略或宿主程序的处理函数）
+        let kind = receiver.kind;
+        if !receivers
+            .iter()
+            .filter_map(Weak::upgrade)
+            .any(|r| r.kind == kind)
+        {
+            let mut previous = self.previous.lock().unwrap();
+            if let Some(index) = previous.iter().position(|(k, _)| *k == kind) {
+                let (_, action) = previous.swap_remove(index);
+                let _ = set_action(kind, &action);
+            }
+        }
+    }
+}
+
+/// Sets the action of the signal, returning the previous one.
+fn set_action(kind: SignalKind, action: &sigaction) -> IoResult<sigaction> {
+    let mut previous: sigaction = unsafe { zeroed() };
+    if unsafe { libc::sigaction(kind.0, action, &mut previous) } < 0 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(previous)
+}
+
+/// Listens for a POSIX signal, like an `EventListener` for signals.
+///
+/// While at least one listener for a signal exists, the action of the signal (such as
+/// terminating the process) is replaced by delivering it to the listeners. Deliveries are
+/// counted, so a signal received while nobody is awaiting `listen` is not lost. When the last
+/// listener for a signal is dropped, the previous action of the signal (the default action,
+/// ignoring it, or a handler installed by the host program) is restored.
+///
+/// ```no_run
+/// use compo::prelude::*;
+///
+/// #[component]
+/// async fn terminal() -> std::io::Result<()> {
+///     let resized = SignalListener::new(SignalKind::WINDOW_CHANGE)?;
+///     loop {
+///         resized.listen().await;
+///         println!("the terminal was resized");
+///     }
+/// }
+/// ```
+pub struct SignalListener {
+    receiver: Arc<Receiver>,
+}
+
+impl SignalListener {
+    /// Starts listening for signals of the given kind.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the signal handler cannot be installed, e.g. for `SIGKILL`.
+    pub fn new(kind: SignalKind) -> IoResult<Self> {
+        Ok(Self {
+            receiver: Driver::get()?.register(kind)?,
+        })
+    }
+
+    /// Returns the kind of signal this listener receives.
+    pub fn kind(&self) -> SignalKind {
+        self.receiver.kind
+    }
+
+    /// Waits for the next delivery of the signal.
+    pub async fn listen(&self) -> SignalKind {
+        poll_fn(|cx| {
+            let receiver = &self.receiver;
+            let take = |pending: &AtomicUsize| {
+                pending
+                    .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1))
+                    .is_ok()
+            };
+            if take(&receiver.pending) {
+                return Poll::Ready(receiver.kind);
+            }
+            receiver.waker.lock().unwrap().replace(cx.waker().clone());
+            // 注册唤醒器之后再检查一次，避免错过在此期间到达的信号
+            if take(&receiver.pending) {
+                Poll::Ready(receiver.kind)
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+impl Drop for SignalListener {
+    fn drop(&mut self) {
+        if let Ok(driver) = Driver::get() {
+            driver.unregister(&self.receiver);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        crate::{
+            runtime::Runtime,
+            signal::{SignalKind, SignalListener},
+        },
+        std::{cell::Cell, rc::Rc},
+    };
+
+    #[test]
+    fn test_signal_listener_receives_signal() {
+        let rt = Runtime::new().unwrap();
+        let received = Rc::new(Cell::new(0));
+        let received2 = received.clone();
+        let listener = SignalListener::new(SignalKind::USER_DEFINED1).unwrap();
+        rt.spawn(async move {
+            for _ in 0..2 {
+                unsafe { libc::raise(libc::SIGUSR1) };
+                assert_eq!(listener.listen().await, SignalKind::USER_DEFINED1);
+                received2.set(received2.get() + 1);
+            }
+        });
+        rt.run_until_done();
+
+        assert_eq!(received.get(), 2);
+    }
+
+    #[test]
+    fn test_signal_listener_restores_previous_action() {
+        let current = || unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            libc::sigaction(libc::SIGWINCH, std::ptr::null(), &mut action);
+            action.sa_sigaction
+        };
+        unsafe { libc::signal(libc::SIGWINCH, libc::SIG_IGN) };
+        let listener = SignalListener::new(SignalKind::WINDOW_CHANGE).unwrap();
+        assert_ne!(current(), libc::SIG_IGN);
+        drop(listener);
+
+        assert_eq!(current(), libc::SIG_IGN);
+    }
+}
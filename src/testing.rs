@@ -0,0 +1,154 @@
+use {
+    crate::{
+        component::{Component, ComponentOutput},
+        runtime::{JoinHandle, Runtime},
+        time::{Duration, Timer},
+    },
+    std::{
+        rc::{Rc, Weak},
+        time::Instant,
+    },
+};
+
+/// A deterministic runtime for testing components.
+///
+/// Tasks are only polled when the test asks for it, and `sleep` is driven by a virtual clock
+/// that only moves forward through `advance`, so timed behavior can be tested without real
+/// delays:
+///
+/// ```
+/// use compo::prelude::*;
+///
+/// let rt = TestRuntime::new();
+/// rt.spawn(sleep(Duration::from_secs(60)));
+/// rt.advance(Duration::from_secs(59));
+/// assert_eq!(rt.runtime().count(), 1);
+/// rt.advance(Duration::from_secs(1));
+/// assert_eq!(rt.runtime().count(), 0);
+/// ```
+pub struct TestRuntime<'a> {
+    rt: Rc<Runtime<'a>>,
+    timer: Rc<Timer>,
+}
+
+impl<'a> Default for TestRuntime<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> TestRuntime<'a> {
+    /// Creates a test runtime whose virtual clock starts at the current time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the underlying runtime cannot be created (see `Runtime::new`).
+    pub fn new() -> Self {
+        let timer = Rc::new(Timer::virtual_clock());
+        Self {
+            rt: Rc::new(Runtime::with_timer(timer.clone()).expect("failed to create a runtime")),
+            timer,
+        }
+    }
+
+    /// Returns the underlying runtime.
+    pub fn runtime(&self) -> &Rc<Runtime<'a>> {
+        &self.rt
+    }
+
+    /// Returns the current time of the virtual clock.
+    pub fn now(&self) -> Instant {
+        self.timer.now()
+    }
+
+    /// Spawns a future on the runtime. It is polled on the next `tick`.
+    pub fn spawn<Fut>(&self, fut: Fut) -> JoinHandle<Fut::Output>
+    where
+        Fut: Future + 'a,
+        Fut::Output: 'a,
+    {
+        self.rt.spawn(fut)
+    }
+
+    /// Creates the root component and spawns `entry` with it, the same way `Loop::run` does.
+    ///
+    /// The returned component must be kept alive for as long as it should keep running.
+    pub fn mount<F, C, O>(&self, entry: F) -> Rc<C>
+    where
+        C: Component<'a> + 'a,
+        F: AsyncFn(Weak<C>) -> O + 'a,
+        O: ComponentOutput,
+    {
+        let c = Rc::new(C::new(Rc::downgrade(&self.rt)));
+        let c_weak = Rc::downgrade(&c);
+        c.render(c.observe(async move { entry(c_weak).await }));
+        c
+    }
+
+    /// Polls every woken task once, like one iteration of the event loop.
+    pub fn tick(&self) {
+        self.rt.poll_all();
+    }
+
+    /// Polls tasks until none of them is woken anymore, without moving the clock.
+    pub fn run_until_stalled(&self) {
+        while self.rt.has_ready() {
+            self.rt.poll_all();
+        }
+    }
+
+    /// Moves the virtual clock forward by `duration`, running the tasks woken by every timer
+    /// that expires on the way, in deadline order.
+    pub fn advance(&self, duration: Duration) {
+        let target = self.timer.now() + duration;
+        loop {
+            self.run_until_stalled();
+            match self.timer.next_deadline() {
+                Some(deadline) if deadline <= target => self.timer.advance_to(deadline),
+                _ => break,
+            }
+            // 推进时钟后需要轮询一次以触发到期的定时器
+            self.rt.poll_all();
+        }
+        self.timer.advance_to(target);
+        self.rt.poll_all();
+        self.run_until_stalled();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        crate::prelude::*,
+        std::{cell::RefCell, rc::Rc},
+    };
+
+    #[component]
+    async fn ticker(#[event] on_tick: u32) {
+        for i in 0..3 {
+            sleep(Duration::from_secs(1)).await;
+            let _ = on_tick.emit(i);
+        }
+    }
+
+    #[test]
+    fn test_advance_fires_timers_in_order() {
+        let rt = TestRuntime::new();
+        let ticks = Rc::new(RefCell::new(Vec::new()));
+        let ticks2 = ticks.clone();
+        let listener = EventListener::default();
+        let c = rt.mount(ticker);
+        c.set_on_tick(&listener);
+        rt.spawn(async move {
+            loop {
+                let tick = *listener.listen().await;
+                ticks2.borrow_mut().push(tick);
+            }
+        });
+
+        rt.advance(Duration::from_millis(500));
+        assert!(ticks.borrow().is_empty());
+        rt.advance(Duration::from_millis(2500));
+        assert_eq!(*ticks.borrow(), vec![0, 1, 2]);
+    }
+}
@@ -1,29 +1,193 @@
 pub use std::time::Duration;
 
 use std::{
+    cell::{Cell, RefCell},
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
     pin::Pin,
-    task::{Context, Poll},
+    rc::{Rc, Weak},
+    task::{Context, Poll, Waker},
     time::Instant,
 };
 
+thread_local! {
+    // 当前正在轮询任务的运行时的定时器
+    static CURRENT: RefCell<Option<Rc<Timer>>> = const { RefCell::new(None) };
+}
+
+/// A deadline heap owned by a runtime, used to wake sleeping tasks.
+///
+/// Entries are removed lazily: cancelling a timer only forgets its waker, and the stale heap
+/// entry is discarded when it reaches the top.
+#[derive(Default)]
+pub(crate) struct Timer {
+    deadlines: RefCell<BinaryHeap<Reverse<(Instant, u64)>>>,
+    wakers: RefCell<HashMap<u64, Waker>>,
+    next_id: Cell<u64>,
+    // 虚拟时钟，只在测试运行时中使用
+    clock: Option<Cell<Instant>>,
+}
+
+impl Timer {
+    /// Creates a timer driven by a virtual clock, which only moves when `advance_to` is called.
+    pub(crate) fn virtual_clock() -> Self {
+        Self {
+            clock: Some(Cell::new(Instant::now())),
+            ..Default::default()
+        }
+    }
+
+    /// Moves the virtual clock forward to `instant`; has no effect on a real-time timer.
+    pub(crate) fn advance_to(&self, instant: Instant) {
+        if let Some(clock) = &self.clock
+            && instant > clock.get()
+        {
+            clock.set(instant);
+        }
+    }
+
+    /// Makes this timer the one `Sleep` futures register with until the guard is dropped.
+    pub(crate) fn enter(self: &Rc<Self>) -> TimerGuard {
+        let previous = CURRENT.with(|c| c.replace(Some(self.clone())));
+        TimerGuard { previous }
+    }
+
+    /// Returns the current time as seen by this timer.
+    pub(crate) fn now(&self) -> Instant {
+        self.clock.as_ref().map_or_else(Instant::now, Cell::get)
+    }
+
+    fn register(&self, deadline: Instant, waker: Waker) -> u64 {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.deadlines.borrow_mut().push(Reverse((deadline, id)));
+        self.wakers.borrow_mut().insert(id, waker);
+        id
+    }
+
+    fn update(&self, id: u64, waker: &Waker) {
+        if let Some(w) = self.wakers.borrow_mut().get_mut(&id)
+            && !w.will_wake(waker)
+        {
+            *w = waker.clone();
+        }
+    }
+
+    fn remove(&self, id: u64) {
+        self.wakers.borrow_mut().remove(&id);
+    }
+
+    /// Wakes every task whose deadline has been reached.
+    pub(crate) fn fire(&self) {
+        let now = self.now();
+        let mut expired = Vec::new();
+        {
+            let mut deadlines = self.deadlines.borrow_mut();
+            let mut wakers = self.wakers.borrow_mut();
+            while let Some(Reverse((deadline, id))) = deadlines.peek().copied()
+                && deadline <= now
+            {
+                deadlines.pop();
+                if let Some(waker) = wakers.remove(&id) {
+                    expired.push(waker);
+                }
+            }
+        }
+        // 释放借用后再唤醒，避免唤醒过程中重新进入定时器
+        for waker in expired {
+            waker.wake();
+        }
+    }
+
+    /// Returns the earliest deadline that still has a task waiting on it.
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        let mut deadlines = self.deadlines.borrow_mut();
+        let wakers = self.wakers.borrow();
+        while let Some(Reverse((deadline, id))) = deadlines.peek().copied() {
+            if wakers.contains_key(&id) {
+                return Some(deadline);
+            }
+            deadlines.pop();
+        }
+
+        None
+    }
+}
+
+/// Restores the previously entered timer when dropped.
+pub(crate) struct TimerGuard {
+    previous: Option<Rc<Timer>>,
+}
+
+impl Drop for TimerGuard {
+    fn drop(&mut self) {
+        CURRENT.with(|c| *c.borrow_mut() = self.previous.take());
+    }
+}
+
+fn current() -> Option<Rc<Timer>> {
+    CURRENT.with(|c| c.borrow().clone())
+}
+
 /// A future that completes after a specified duration has elapsed.
+///
+/// When polled by a compo runtime the sleep registers with the runtime's timer, so the task is
+/// only woken once the deadline has passed. Polled by any other executor, it falls back to
+/// waking itself immediately on every poll.
+///
+/// A sleep created outside of a running task starts counting when it is first polled.
 pub struct Sleep {
+    /// How long to sleep, used if the deadline is not known yet
+    duration: Duration,
     /// The time at which the sleep should complete
-    deadline: Instant,
+    deadline: Option<Instant>,
+    /// The timer this sleep is registered with, and its entry id
+    entry: Option<(Weak<Timer>, u64)>,
 }
 
 impl Future for Sleep {
     type Output = ();
 
-    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.get_mut();
+        let timer = current();
+        let now = timer.as_ref().map_or_else(Instant::now, |t| t.now());
 
         // Check if the deadline has been reached
-        if Instant::now() >= this.deadline {
-            Poll::Ready(())
+        let deadline = *this.deadline.get_or_insert(now + this.duration);
+        if now >= deadline {
+            this.cancel();
+            return Poll::Ready(());
+        }
+
+        if let Some((registered, id)) = &this.entry
+            && let Some(registered) = registered.upgrade()
+        {
+            registered.update(*id, cx.waker());
+        } else if let Some(timer) = timer {
+            let id = timer.register(deadline, cx.waker().clone());
+            this.entry = Some((Rc::downgrade(&timer), id));
         } else {
-            Poll::Pending
+            cx.waker().wake_by_ref();
         }
+
+        Poll::Pending
+    }
+}
+
+impl Sleep {
+    fn cancel(&mut self) {
+        if let Some((timer, id)) = self.entry.take()
+            && let Some(timer) = timer.upgrade()
+        {
+            timer.remove(id);
+        }
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.cancel();
     }
 }
 
@@ -41,6 +205,28 @@ impl Future for Sleep {
 /// ```
 pub fn sleep(duration: Duration) -> Sleep {
     Sleep {
-        deadline: Instant::now() + duration,
+        duration,
+        deadline: current().map(|t| t.now() + duration),
+        entry: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        crate::{runtime::Runtime, time::sleep},
+        std::time::{Duration, Instant},
+    };
+
+    #[test]
+    fn test_sleep_wakes_parked_runtime() {
+        let rt = Runtime::new().unwrap();
+        rt.spawn(sleep(Duration::from_millis(20)));
+        let start = Instant::now();
+        let polls = rt.run_until_done();
+
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        // 休眠期间线程被挂起，而不是反复轮询
+        assert!(polls < 5);
     }
 }